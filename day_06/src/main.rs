@@ -1,23 +1,44 @@
 use std::io::prelude::*;
+use std::io::{self, BufWriter};
 use std::fs::File;
 use std::cmp;
+use std::env;
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const TEST_INPUT: &str = "1, 1\n\
+                              1, 6\n\
+                              8, 3\n\
+                              3, 4\n\
+                              5, 5\n\
+                              8, 9";
+
     #[test]
     fn test_both_halves() {
-        let input = String::from("1, 1\n\
-                                  1, 6\n\
-                                  8, 3\n\
-                                  3, 4\n\
-                                  5, 5\n\
-                                  8, 9");
+        let input = String::from(TEST_INPUT);
         let (coords, max_x, max_y) = parse_input(input);
         assert_eq!(largest_voronoi_area(&coords, max_x , max_y), 17);
         assert_eq!(central_area(&coords, max_x, max_y, 32), 16);
     }
+
+    #[test]
+    fn test_export() {
+        let input = String::from(TEST_INPUT);
+        let (coords, max_x, max_y) = parse_input(input);
+        let pixels = render(&coords, max_x, max_y, 32);
+        assert_eq!(pixels.len(), max_x*max_y);
+        assert_eq!(pixels[1 + max_x], [0, 0, 0]);
+        // Site 4 (3, 4) is finite, site 1 (1, 1) isn't
+        assert!(pixels[2 + 4*max_x].iter().max() > pixels[0].iter().max());
+
+        let mut image = Vec::new();
+        write_ppm(&mut image, max_x, max_y, &pixels).unwrap();
+        let header = format!("P6\n{} {}\n255\n", max_x, max_y);
+        assert!(image.starts_with(header.as_bytes()));
+        assert_eq!(image.len(), header.len() + 3*max_x*max_y);
+    }
 }
 
 #[derive(PartialEq, Eq, Copy, Clone)]
@@ -33,12 +54,12 @@ fn parse_line(line: &str, id: usize) -> Coord {
               .parse::<usize>().unwrap();
     let y = xy.next().unwrap()
               .parse::<usize>().unwrap();
-    Coord{ id: id, x: x, y: y }
+    Coord{ id, x, y }
 }
 
 fn distance(a: Coord, b: Coord) -> usize {
-    return ((a.x as isize - b.x as isize).abs()
-         + (a.y as isize - b.y as isize).abs()) as usize;
+    ((a.x as isize - b.x as isize).abs()
+     + (a.y as isize - b.y as isize).abs()) as usize
 }
 
 fn parse_input(input: String) -> (Vec<Coord>, usize, usize) {
@@ -59,14 +80,15 @@ fn parse_input(input: String) -> (Vec<Coord>, usize, usize) {
     (coords, max_x, max_y)
 }
 
-fn largest_voronoi_area(coords: &Vec<Coord>, max_x: usize,
-                        max_y: usize) -> usize {
-    let mut grid = vec![vec![0 as usize; max_y]; max_x];
-    let mut id_counts = vec![0; coords.len()+1];
-    let mut on_edge = vec![0; coords.len()+1];
-    for x in 0..max_x {
-        for y in 0..max_y {
-            let mut xy = Coord { x: x, y: y, id: 0};
+fn voronoi(coords: &[Coord], max_x: usize,
+           max_y: usize) -> (Vec<Vec<usize>>, Vec<bool>) {
+    // Returns the ownership grid along with which ids touch the edge,
+    // i.e. have infinite area
+    let mut grid = vec![vec![0; max_y]; max_x];
+    let mut infinite = vec![false; coords.len()+1];
+    for (x, column) in grid.iter_mut().enumerate() {
+        for (y, owner) in column.iter_mut().enumerate() {
+            let xy = Coord { x, y, id: 0};
 
             let mut min_distance = max_y+max_x;
             let mut min_id = 0;
@@ -77,36 +99,42 @@ fn largest_voronoi_area(coords: &Vec<Coord>, max_x: usize,
                     min_id = coord.id;
                 }
             }
-            grid[x][y] = min_id;
+            *owner = min_id;
 
             if x == 0 || y == 0 || x == max_x-1 || y == max_y-1 {
-                on_edge[min_id] = 1;
-                id_counts[min_id] = 0;
-            }
-            if on_edge[min_id] != 1 {
-                id_counts[min_id] += 1;
+                infinite[min_id] = true;
             }
         }
     }
+    (grid, infinite)
+}
 
-    let mut max_count = 0; 
-    for id in 0..coords.len()+1 {
-        max_count = cmp::max(max_count, id_counts[id]);
+fn largest_voronoi_area(coords: &[Coord], max_x: usize,
+                        max_y: usize) -> usize {
+    let (grid, infinite) = voronoi(coords, max_x, max_y);
+    let mut id_counts = vec![0; coords.len()+1];
+    for column in &grid {
+        for &owner in column {
+            if !infinite[owner] {
+                id_counts[owner] += 1;
+            }
+        }
     }
-    max_count
+
+    id_counts.into_iter().max().unwrap_or(0)
+}
+
+fn total_distance(coords: &[Coord], xy: Coord) -> usize {
+    coords.iter().map(|coord| distance(*coord, xy)).sum()
 }
 
-fn central_area(coords: &Vec<Coord>, max_x: usize, max_y: usize,
+fn central_area(coords: &[Coord], max_x: usize, max_y: usize,
                 max_distance: usize) -> usize {
 
     let mut central_area = 0;
     for x in 0..max_x {
         for y in 0..max_y {
-            let mut total_distance = 0;
-            let xy = Coord { x: x, y: y, id: 0};
-            for coord in coords {
-                total_distance += distance(*coord, xy);
-            }
+            let total_distance = total_distance(coords, Coord { x, y, id: 0});
             if total_distance < max_distance {
                 let vertical_edge = x == 0 || x == max_x-1;
                 let horizontal_edge = y == 0 || y == max_y-1;
@@ -130,6 +158,68 @@ fn central_area(coords: &Vec<Coord>, max_x: usize, max_y: usize,
     central_area
 }
 
+fn site_colour(id: usize, num_sites: usize, infinite: bool) -> [u8; 3] {
+    // Spread hues evenly over the sites, infinite regions are drawn darker
+    let hue = 6.0 * id as f64 / (num_sites + 1) as f64;
+    let value = if infinite { 0.45 } else { 0.95 };
+    let saturation = 0.7;
+
+    let chroma = value * saturation;
+    let secondary = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, secondary, 0.0),
+        1 => (secondary, chroma, 0.0),
+        2 => (0.0, chroma, secondary),
+        3 => (0.0, secondary, chroma),
+        4 => (secondary, 0.0, chroma),
+        _ => (chroma, 0.0, secondary),
+    };
+    let base = value - chroma;
+    let to_byte = |c: f64| ((c + base) * 255.0) as u8;
+    [to_byte(r), to_byte(g), to_byte(b)]
+}
+
+fn render(coords: &[Coord], max_x: usize, max_y: usize,
+          max_distance: usize) -> Vec<[u8; 3]> {
+    // Pixels in row-major order, the central region is lightened
+    // and each site is marked with a black pixel
+    let (grid, infinite) = voronoi(coords, max_x, max_y);
+    let mut pixels = Vec::with_capacity(max_x*max_y);
+    for y in 0..max_y {
+        for (x, column) in grid.iter().enumerate() {
+            let owner = column[y];
+            let mut pixel = site_colour(owner, coords.len(), infinite[owner]);
+            if total_distance(coords, Coord { x, y, id: 0}) < max_distance {
+                for channel in &mut pixel {
+                    *channel = 255 - (255 - *channel) / 3;
+                }
+            }
+            pixels.push(pixel);
+        }
+    }
+
+    for coord in coords {
+        pixels[coord.x + coord.y*max_x] = [0, 0, 0];
+    }
+    pixels
+}
+
+fn write_ppm<W: Write>(out: &mut W, width: usize, height: usize,
+                       pixels: &[[u8; 3]]) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    for pixel in pixels {
+        out.write_all(pixel)?;
+    }
+    Ok(())
+}
+
+fn export_image(path: &str, coords: &[Coord], max_x: usize, max_y: usize,
+                max_distance: usize) -> io::Result<()> {
+    let pixels = render(coords, max_x, max_y, max_distance);
+    let mut f = BufWriter::new(File::create(path)?);
+    write_ppm(&mut f, max_x, max_y, &pixels)
+}
+
 fn main() {
     let mut input = String::new();
     let mut f = File::open("input").expect("Failed to open input.");
@@ -141,4 +231,10 @@ fn main() {
 
 
     println!("central_area: {}", central_area(&coords, max_x, max_y, 10_000));
+
+    if let Some(path) = env::args().nth(1) {
+        export_image(&path, &coords, max_x, max_y, 10_000)
+            .expect("Failed to write image.");
+        println!("Wrote voronoi diagram to {}", path);
+    }
 }
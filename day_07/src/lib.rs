@@ -0,0 +1,776 @@
+use std::cmp;
+use std::cmp::Reverse;
+use std::fmt;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::BinaryHeap;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_INPUT: &str = "Step C must be finished before step A can begin.\n\
+                              Step C must be finished before step F can begin.\n\
+                              Step A must be finished before step B can begin.\n\
+                              Step A must be finished before step D can begin.\n\
+                              Step B must be finished before step E can begin.\n\
+                              Step D must be finished before step E can begin.\n\
+                              Step F must be finished before step E can begin.";
+
+    #[test]
+    fn test_both_halves() {
+        let graph = TaskGraph::parse(TEST_INPUT).unwrap();
+        assert_eq!(graph.validate(), Ok(()));
+        assert_eq!(graph.topological_order().unwrap().concat(), "CABDFE");
+
+        let schedule = graph.schedule(2, letter_duration(0)).unwrap();
+        assert_eq!(schedule.makespan, 15);
+        let order: Vec<&str> = schedule.assignments.iter()
+                                       .map(|a| a.task.as_str())
+                                       .collect();
+        assert_eq!(order, vec!["C", "A", "F", "B", "D", "E"]);
+        assert_eq!(schedule.assignments[2],
+                   Assignment { task: String::from("F"), worker: 1,
+                                start: 3, finish: 9 });
+    }
+
+    #[test]
+    fn test_timeline_output() {
+        let graph = TaskGraph::parse(TEST_INPUT).unwrap();
+        let schedule = graph.schedule(2, letter_duration(0)).unwrap();
+        assert_eq!(schedule.idle_times(), vec![0, 9]);
+        assert_eq!(schedule.critical_path, vec!["C", "F", "E"]);
+        assert_eq!(schedule.critical_length, 14);
+
+        assert_eq!(schedule.gantt(80),
+                   "Time units per column: 1\n  \
+                      0 |CCCABBDDDDEEEEE| idle 0\n  \
+                      1 |...FFFFFF......| idle 9\n\
+                    Critical path (14): C -> F -> E\n");
+        assert_eq!(schedule.gantt(5).lines().nth(1),
+                   Some("  0 |CBDEE| idle 0"));
+
+        assert_eq!(schedule.to_json(),
+                   "{\"policy\":\"alphabetical\",\"num_workers\":2,\"makespan\":15,\
+                    \"critical_path\":[\"C\",\"F\",\"E\"],\"critical_length\":14,\
+                    \"workers\":[{\"worker\":0,\"idle\":0,\"tasks\":[\
+                    {\"task\":\"C\",\"start\":0,\"finish\":3},\
+                    {\"task\":\"A\",\"start\":3,\"finish\":4},\
+                    {\"task\":\"B\",\"start\":4,\"finish\":6},\
+                    {\"task\":\"D\",\"start\":6,\"finish\":10},\
+                    {\"task\":\"E\",\"start\":10,\"finish\":15}]},\
+                    {\"worker\":1,\"idle\":9,\"tasks\":[\
+                    {\"task\":\"F\",\"start\":3,\"finish\":9}]}]}");
+        assert_eq!(json_string("a\"b\\"), "\"a\\\"b\\\\\"");
+    }
+
+    #[test]
+    fn test_policies() {
+        let mut graph = TaskGraph::new();
+        for task in &["A", "B", "C"] {
+            graph.add_task(task);
+        }
+        graph.add_dependency("Y", "Z");
+
+        let makespans = graph.compare_policies(2, letter_duration(0),
+                                               &standard_policies())
+                             .unwrap();
+        assert_eq!(makespans,
+                   vec![(String::from("alphabetical"), 53),
+                        (String::from("longest-task-first"), 51),
+                        (String::from("most-dependents-first"), 51),
+                        (String::from("critical-path-first"), 51)]);
+
+        let priorities = CriticalPathFirst.priorities(&graph,
+                                                      &letter_duration(0));
+        assert_eq!(priorities["Z"], 51);
+        assert_eq!(priorities["Y"], 25);
+    }
+
+    #[test]
+    fn test_named_tasks() {
+        let mut graph = TaskGraph::new();
+        graph.add_dependency("deploy", "build");
+        graph.add_dependency("deploy", "test");
+        graph.add_dependency("test", "build");
+        graph.add_task("docs");
+        assert_eq!(graph.topological_order().unwrap(),
+                   vec!["build", "docs", "test", "deploy"]);
+
+        let schedule = graph.schedule(3, |task: &str| task.len() as u32)
+                            .unwrap();
+        assert_eq!(schedule.makespan, 15);
+        assert_eq!(schedule.assignments.len(), 4);
+        assert_eq!(schedule.gantt(80),
+                   "Time units per column: 1\n  \
+                      0 |bbbbbtttteeeeee| idle 0\n  \
+                      1 |dddd...........| idle 11\n  \
+                      2 |...............| idle 15\n\
+                    Legend: b = build, e = deploy, d = docs, t = test\n\
+                    Critical path (15): build -> test -> deploy\n");
+
+        let duration = letter_duration(60);
+        assert_eq!((duration("a"), duration("Z"), duration("1"), duration("")),
+                   (61, 86, 61, 61));
+        let graph = TaskGraph::parse("Step 1 must be finished before step 2 can begin.")
+                              .unwrap();
+        assert_eq!(graph.schedule(2, letter_duration(60)).unwrap().makespan, 122);
+        assert_eq!(graph.schedule(0, letter_duration(60)),
+                   Err(vec![GraphError::NoWorkers]));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let input = "Step A must be finished before step B can begin.\n\
+                     Step A must be done before B.";
+        assert!(TaskGraph::parse(input).unwrap_err().starts_with("Line 2"));
+    }
+
+    #[test]
+    fn test_validation() {
+        let input = "Step A must be finished before step B can begin.\n\
+                     Step B must be finished before step C can begin.\n\
+                     Step C must be finished before step B can begin.\n\
+                     Step C must be finished before step D can begin.\n\
+                     Step A must be finished before step B can begin.\n\
+                     Step E must be finished before step E can begin.";
+        let graph = TaskGraph::parse(input).unwrap();
+        let cycle = |path: &[&str]| {
+            GraphError::Cycle(path.iter().map(|t| t.to_string()).collect())
+        };
+
+        let blocking = vec![cycle(&["B", "C", "B"]), cycle(&["E", "E"]),
+                            GraphError::Unreachable(String::from("D"))];
+        assert_eq!(graph.topological_order(), Err(blocking.clone()));
+        assert_eq!(graph.schedule(2, letter_duration(0)), Err(blocking.clone()));
+
+        let duration = letter_duration(0);
+        assert_eq!(MostDependentsFirst.priorities(&graph, &duration),
+                   vec![("A", 1)].into_iter().collect());
+        assert_eq!(CriticalPathFirst.priorities(&graph, &duration),
+                   vec![("A", 1)].into_iter().collect());
+
+        let mut all = blocking;
+        all.push(GraphError::DuplicateEdge(String::from("A"),
+                                           String::from("B")));
+        assert_eq!(graph.validate(), Err(all));
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Relations {
+    antidependencies: BTreeSet<String>,
+    dependencies: BTreeSet<String>,
+}
+
+/// Tasks and the dependencies between them, keyed by name.
+#[derive(Debug, Clone, Default)]
+pub struct TaskGraph {
+    tasks: BTreeMap<String, Relations>,
+    duplicate_edges: Vec<(String, String)>,
+}
+
+/// Problems found in a graph. Cycles and unreachable tasks prevent a
+/// complete order, duplicate edges are only reported. Scheduling also fails
+/// without any workers.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum GraphError {
+    /// Tasks that depend on each other, starting and ending on the same
+    /// task. A task depending on itself is a cycle of length one.
+    Cycle(Vec<String>),
+    /// A task that isn't on a cycle, but waits on one.
+    Unreachable(String),
+    /// A `(dependency, task)` pair that was given more than once.
+    DuplicateEdge(String, String),
+    /// A schedule asked for with no workers to run it.
+    NoWorkers,
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GraphError::Cycle(ref path) =>
+                write!(f, "Cycle: {}", path.join(" -> ")),
+            GraphError::Unreachable(ref task) =>
+                write!(f, "Step {} waits on a cycle", task),
+            GraphError::DuplicateEdge(ref dependency, ref task) =>
+                write!(f, "Step {} is required by step {} more than once",
+                       dependency, task),
+            GraphError::NoWorkers =>
+                write!(f, "Steps can't be scheduled without workers"),
+        }
+    }
+}
+
+/// A single task run on a worker, from `start` until `finish`.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Assignment {
+    pub task: String,
+    pub worker: usize,
+    pub start: u32,
+    pub finish: u32,
+}
+
+/// Every assignment in the order they were started, and the total time.
+/// The critical path is the longest chain of dependent tasks, its length
+/// bounds the makespan from below regardless of the number of workers.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub struct Schedule {
+    pub policy: String,
+    pub num_workers: usize,
+    pub assignments: Vec<Assignment>,
+    pub makespan: u32,
+    pub critical_path: Vec<String>,
+    pub critical_length: u32,
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}",
+                                                           c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+impl Schedule {
+    /// Assignments run by `worker`, in order.
+    pub fn timeline(&self, worker: usize) -> Vec<&Assignment> {
+        self.assignments.iter().filter(|a| a.worker == worker).collect()
+    }
+
+    /// Time each worker spends without a task before the makespan.
+    pub fn idle_times(&self) -> Vec<u32> {
+        let mut idle = vec![self.makespan; self.num_workers];
+        for assignment in &self.assignments {
+            idle[assignment.worker] -= assignment.finish - assignment.start;
+        }
+        idle
+    }
+
+    /// A different symbol for each task, the first character of its name
+    /// not taken by a task started earlier.
+    fn symbols(&self) -> BTreeMap<&str, char> {
+        let spare = ('a'..='z').chain('A'..='Z').chain('0'..='9');
+        let mut symbols = BTreeMap::new();
+        let mut used = BTreeSet::new();
+        for assignment in &self.assignments {
+            let task = assignment.task.as_str();
+            if symbols.contains_key(task) {
+                continue;
+            }
+            let symbol = task.chars()
+                             .chain(spare.clone())
+                             .find(|&c| !used.contains(&c) && c != '.' && c != '|'
+                                        && !c.is_whitespace())
+                             .unwrap_or('?');
+            used.insert(symbol);
+            symbols.insert(task, symbol);
+        }
+        symbols
+    }
+
+    /// One row per worker, each column covering the same slice of time and
+    /// showing the symbol of the task run during it. Tasks with names longer
+    /// than their symbol are listed in a legend.
+    pub fn gantt(&self, max_width: usize) -> String {
+        let makespan = self.makespan as usize;
+        let scale = cmp::max(1, makespan.div_ceil(cmp::max(1, max_width)));
+        let columns = makespan.div_ceil(scale);
+
+        let symbols = self.symbols();
+        let mut chart = format!("Time units per column: {}\n", scale);
+        for (worker, idle) in self.idle_times().into_iter().enumerate() {
+            let mut row = vec!['.'; columns];
+            for assignment in self.timeline(worker) {
+                let symbol = symbols[assignment.task.as_str()];
+                let first = assignment.start as usize / scale;
+                let last = (assignment.finish as usize).saturating_sub(1) / scale;
+                for cell in row.iter_mut().take(last + 1).skip(first) {
+                    *cell = symbol;
+                }
+            }
+            let row: String = row.into_iter().collect();
+            chart.push_str(&format!("{:>3} |{}| idle {}\n", worker, row, idle));
+        }
+        if symbols.iter().any(|(task, symbol)| task.chars().ne(Some(*symbol))) {
+            let legend: Vec<String> = symbols.iter()
+                .map(|(task, symbol)| format!("{} = {}", symbol, task))
+                .collect();
+            chart.push_str(&format!("Legend: {}\n", legend.join(", ")));
+        }
+        chart.push_str(&format!("Critical path ({}): {}\n",
+                                self.critical_length,
+                                self.critical_path.join(" -> ")));
+        chart
+    }
+
+    pub fn to_json(&self) -> String {
+        let critical_path: Vec<String> = self.critical_path.iter()
+                                             .map(|t| json_string(t))
+                                             .collect();
+        let mut workers = Vec::new();
+        for (worker, idle) in self.idle_times().into_iter().enumerate() {
+            let tasks: Vec<String> = self.timeline(worker).iter()
+                .map(|a| format!("{{\"task\":{},\"start\":{},\"finish\":{}}}",
+                                 json_string(&a.task), a.start, a.finish))
+                .collect();
+            workers.push(format!("{{\"worker\":{},\"idle\":{},\"tasks\":[{}]}}",
+                                 worker, idle, tasks.join(",")));
+        }
+        format!("{{\"policy\":{},\"num_workers\":{},\"makespan\":{},\
+                 \"critical_path\":[{}],\"critical_length\":{},\"workers\":[{}]}}",
+                json_string(&self.policy), self.num_workers, self.makespan, critical_path.join(","),
+                self.critical_length, workers.join(","))
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (worker, idle) in self.idle_times().into_iter().enumerate() {
+            let tasks: Vec<String> = self.timeline(worker).iter()
+                .map(|a| format!("{} {}-{}", a.task, a.start, a.finish))
+                .collect();
+            writeln!(f, "Worker {}: {} (idle {})", worker, tasks.join(", "),
+                     idle)?;
+        }
+        Ok(())
+    }
+}
+
+type ReadyQueue<'a> = BinaryHeap<(u64, Reverse<&'a str>)>;
+
+/// Decides which ready task an idle worker starts next. Tasks with higher
+/// priorities go first, ties are broken alphabetically.
+pub trait Policy {
+    fn name(&self) -> &str;
+    fn priorities<'a>(&self, graph: &'a TaskGraph,
+                      duration: &dyn Fn(&str) -> u32)
+        -> BTreeMap<&'a str, u64>;
+}
+
+/// The AoC rule, purely alphabetical.
+pub struct Alphabetical;
+
+/// Longest duration first.
+pub struct LongestTaskFirst;
+
+/// Most tasks waiting on it, directly or indirectly, first.
+pub struct MostDependentsFirst;
+
+/// Longest remaining chain of dependents, including itself, first.
+pub struct CriticalPathFirst;
+
+impl Policy for Alphabetical {
+    fn name(&self) -> &str {
+        "alphabetical"
+    }
+
+    fn priorities<'a>(&self, graph: &'a TaskGraph,
+                      _duration: &dyn Fn(&str) -> u32)
+            -> BTreeMap<&'a str, u64> {
+        graph.tasks().map(|task| (task, 0)).collect()
+    }
+}
+
+impl Policy for LongestTaskFirst {
+    fn name(&self) -> &str {
+        "longest-task-first"
+    }
+
+    fn priorities<'a>(&self, graph: &'a TaskGraph,
+                      duration: &dyn Fn(&str) -> u32)
+            -> BTreeMap<&'a str, u64> {
+        graph.tasks().map(|task| (task, u64::from(duration(task)))).collect()
+    }
+}
+
+impl Policy for MostDependentsFirst {
+    fn name(&self) -> &str {
+        "most-dependents-first"
+    }
+
+    fn priorities<'a>(&self, graph: &'a TaskGraph,
+                      _duration: &dyn Fn(&str) -> u32)
+            -> BTreeMap<&'a str, u64> {
+        // Tasks blocked by a cycle are left out of the order, and skipped
+        let mut dependents: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for &task in graph.partial_order().iter().rev() {
+            let mut all = BTreeSet::new();
+            for antidep in graph.antidependencies(task) {
+                all.insert(antidep);
+                if let Some(indirect) = dependents.get(antidep) {
+                    all.extend(indirect.iter().cloned());
+                }
+            }
+            dependents.insert(task, all);
+        }
+        dependents.into_iter()
+                  .map(|(task, all)| (task, all.len() as u64))
+                  .collect()
+    }
+}
+
+impl Policy for CriticalPathFirst {
+    fn name(&self) -> &str {
+        "critical-path-first"
+    }
+
+    fn priorities<'a>(&self, graph: &'a TaskGraph,
+                      duration: &dyn Fn(&str) -> u32)
+            -> BTreeMap<&'a str, u64> {
+        // Tasks blocked by a cycle are left out of the order, and skipped
+        let mut remaining = BTreeMap::new();
+        for &task in graph.partial_order().iter().rev() {
+            let longest = graph.antidependencies(task)
+                               .filter_map(|antidep| remaining.get(antidep).cloned())
+                               .max()
+                               .unwrap_or(0);
+            remaining.insert(task, longest + u64::from(duration(task)));
+        }
+        remaining
+    }
+}
+
+/// One of each of the policies above.
+pub fn standard_policies() -> Vec<Box<dyn Policy>> {
+    vec![Box::new(Alphabetical), Box::new(LongestTaskFirst),
+         Box::new(MostDependentsFirst), Box::new(CriticalPathFirst)]
+}
+
+/// Duration of `base` plus the position of the task's first letter in the
+/// alphabet, e.g. `A` takes `base + 1`. Tasks that don't start with a letter
+/// take as long as `A`.
+pub fn letter_duration(base: u32) -> impl Fn(&str) -> u32 {
+    move |task: &str| {
+        let position = task.bytes().next()
+                           .filter(|letter| letter.is_ascii_alphabetic())
+                           .map_or(0, |letter| letter.to_ascii_uppercase() - b'A');
+        base + u32::from(position) + 1
+    }
+}
+
+impl TaskGraph {
+    pub fn new() -> TaskGraph {
+        TaskGraph { tasks: BTreeMap::new(), duplicate_edges: Vec::new() }
+    }
+
+    /// Parses lines of the form
+    /// `Step <dependency> must be finished before step <task> can begin.`
+    pub fn parse(input: &str) -> Result<TaskGraph, String> {
+        let mut graph = TaskGraph::new();
+        for (line_num, line) in input.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["Step", dependency, "must", "be", "finished", "before",
+                 "step", task, "can", "begin."] => {
+                    graph.add_dependency(task, dependency);
+                },
+                _ => return Err(format!("Line {}: malformed instruction '{}'",
+                                        line_num + 1, line)),
+            }
+        }
+        Ok(graph)
+    }
+
+    pub fn add_task(&mut self, task: &str) {
+        self.tasks.entry(task.to_string()).or_default();
+    }
+
+    /// Makes `task` wait for `dependency`, adding either if missing.
+    pub fn add_dependency(&mut self, task: &str, dependency: &str) {
+        let added = self.tasks.entry(task.to_string()).or_default()
+                        .dependencies.insert(dependency.to_string());
+        if !added {
+            self.duplicate_edges.push((dependency.to_string(),
+                                       task.to_string()));
+        }
+        self.tasks.entry(dependency.to_string()).or_default()
+            .antidependencies.insert(task.to_string());
+    }
+
+    pub fn tasks(&self) -> impl Iterator<Item = &str> {
+        self.tasks.keys().map(|task| task.as_str())
+    }
+
+    pub fn dependencies(&self, task: &str) -> impl Iterator<Item = &str> {
+        self.tasks[task].dependencies.iter().map(|dep| dep.as_str())
+    }
+
+    pub fn antidependencies(&self, task: &str) -> impl Iterator<Item = &str> {
+        self.tasks[task].antidependencies.iter().map(|dep| dep.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    fn unmet_dependencies(&self) -> BTreeMap<&str, usize> {
+        self.tasks.iter()
+            .map(|(task, rels)| (task.as_str(), rels.dependencies.len()))
+            .collect()
+    }
+
+    fn release<'a>(&'a self, task: &str, unmet: &mut BTreeMap<&'a str, usize>,
+                   priorities: &BTreeMap<&str, u64>,
+                   ready: &mut ReadyQueue<'a>) {
+        for antidep in self.antidependencies(task) {
+            let count = unmet.get_mut(antidep).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                ready.push((priorities[antidep], Reverse(antidep)));
+            }
+        }
+    }
+
+    fn initially_ready(&self, priorities: &BTreeMap<&str, u64>)
+            -> ReadyQueue<'_> {
+        self.tasks.iter()
+            .filter(|(_, rels)| rels.dependencies.is_empty())
+            .map(|(task, _)| (priorities[task.as_str()], Reverse(task.as_str())))
+            .collect()
+    }
+
+    fn partial_order(&self) -> Vec<&str> {
+        let priorities = Alphabetical.priorities(self, &|_| 0);
+        let mut unmet = self.unmet_dependencies();
+        let mut ready = self.initially_ready(&priorities);
+
+        let mut order = Vec::new();
+        while let Some((_, Reverse(task))) = ready.pop() {
+            order.push(task);
+            self.release(task, &mut unmet, &priorities, &mut ready);
+        }
+        order
+    }
+
+    fn find_cycles(&self) -> Vec<Vec<String>> {
+        // Depth first search along antidependencies, every edge back onto
+        // the current path closes a cycle. Tasks map to whether they're done.
+        let mut visited: BTreeMap<&str, bool> = BTreeMap::new();
+        let mut cycles = Vec::new();
+        for root in self.tasks() {
+            if visited.contains_key(root) {
+                continue;
+            }
+
+            visited.insert(root, false);
+            let mut path = vec![root];
+            let mut stack = vec![self.antidependencies(root)];
+            while let Some(children) = stack.last_mut() {
+                match children.next() {
+                    Some(next) => match visited.get(next) {
+                        None => {
+                            visited.insert(next, false);
+                            path.push(next);
+                            stack.push(self.antidependencies(next));
+                        },
+                        Some(false) => {
+                            let start = path.iter()
+                                            .position(|t| *t == next)
+                                            .unwrap();
+                            let mut cycle: Vec<String> =
+                                path[start..].iter()
+                                             .map(|t| t.to_string())
+                                             .collect();
+                            cycle.push(next.to_string());
+                            cycles.push(cycle);
+                        },
+                        Some(true) => {},
+                    },
+                    None => {
+                        stack.pop();
+                        visited.insert(path.pop().unwrap(), true);
+                    },
+                }
+            }
+        }
+        cycles
+    }
+
+    fn blocking_errors(&self, order: &[&str]) -> Vec<GraphError> {
+        if order.len() == self.len() {
+            return Vec::new();
+        }
+
+        let cycles = self.find_cycles();
+        let mut errors = Vec::new();
+        for task in self.tasks() {
+            let in_cycle = cycles.iter()
+                                 .any(|cycle| cycle.iter().any(|t| t == task));
+            if !in_cycle && !order.contains(&task) {
+                errors.push(GraphError::Unreachable(task.to_string()));
+            }
+        }
+
+        let mut cycles: Vec<GraphError> = cycles.into_iter()
+                                                .map(GraphError::Cycle)
+                                                .collect();
+        cycles.append(&mut errors);
+        cycles
+    }
+
+    /// Reports every cycle, task blocked by a cycle and duplicate edge.
+    pub fn validate(&self) -> Result<(), Vec<GraphError>> {
+        let mut errors = self.blocking_errors(&self.partial_order());
+        for (dependency, task) in &self.duplicate_edges {
+            errors.push(GraphError::DuplicateEdge(dependency.clone(),
+                                                  task.clone()));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Order of execution for a single worker, always picking the
+    /// lexicographically first task that is ready. Fails if any task can't
+    /// be ordered.
+    pub fn topological_order(&self) -> Result<Vec<&str>, Vec<GraphError>> {
+        let order = self.partial_order();
+        let errors = self.blocking_errors(&order);
+        if errors.is_empty() {
+            Ok(order)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Longest chain of dependent tasks when each takes `duration(task)`,
+    /// along with its total duration.
+    pub fn critical_path<F>(&self, duration: F)
+            -> Result<(Vec<&str>, u32), Vec<GraphError>>
+            where F: Fn(&str) -> u32 {
+        let order = self.topological_order()?;
+
+        // Earliest finish of each task, and the dependency that delays it most
+        let mut finish: BTreeMap<&str, (u32, Option<&str>)> = BTreeMap::new();
+        for &task in &order {
+            let latest = self.dependencies(task)
+                             .map(|dep| (finish[dep].0, Reverse(dep)))
+                             .max();
+            let (start, previous) = match latest {
+                Some((time, Reverse(dep))) => (time, Some(dep)),
+                None => (0, None),
+            };
+            finish.insert(task, (start + duration(task), previous));
+        }
+
+        let last = order.iter()
+                        .map(|&task| (finish[task].0, Reverse(task)))
+                        .max();
+        let (length, mut task) = match last {
+            Some((length, Reverse(task))) => (length, Some(task)),
+            None => (0, None),
+        };
+
+        let mut path = Vec::new();
+        while let Some(current) = task {
+            path.push(current);
+            task = finish[current].1;
+        }
+        path.reverse();
+        Ok((path, length))
+    }
+
+    /// Simulates `num_workers` workers, each idle worker picking the
+    /// lexicographically first ready task, which takes `duration(task)`.
+    /// Fails, like `topological_order`, if any task can't be ordered.
+    pub fn schedule<F>(&self, num_workers: usize, duration: F)
+            -> Result<Schedule, Vec<GraphError>>
+            where F: Fn(&str) -> u32 {
+        self.schedule_with(num_workers, duration, &Alphabetical)
+    }
+
+    /// Like `schedule`, but idle workers pick the ready task `policy`
+    /// prioritises highest. Fails if there are no workers.
+    pub fn schedule_with<F, P>(&self, num_workers: usize, duration: F,
+                               policy: &P)
+            -> Result<Schedule, Vec<GraphError>>
+            where F: Fn(&str) -> u32, P: Policy + ?Sized {
+        if num_workers == 0 {
+            return Err(vec![GraphError::NoWorkers]);
+        }
+        let (critical_path, critical_length) = self.critical_path(&duration)?;
+
+        let priorities = policy.priorities(self, &duration);
+        let mut unmet = self.unmet_dependencies();
+        let mut ready = self.initially_ready(&priorities);
+        let mut workers: Vec<Option<(&str, u32)>> = vec![None; num_workers];
+        let mut assignments = Vec::new();
+        let mut time = 0;
+
+        loop {
+            for (i, worker) in workers.iter_mut().enumerate() {
+                if worker.is_some() {
+                    continue;
+                }
+                let task = match ready.pop() {
+                    Some((_, Reverse(task))) => task,
+                    None => break,
+                };
+                let finish = time + duration(task);
+                *worker = Some((task, finish));
+                assignments.push(Assignment { task: task.to_string(),
+                                              worker: i, start: time,
+                                              finish });
+            }
+
+            let next_finish = workers.iter()
+                                     .filter_map(|w| w.map(|(_, finish)| finish))
+                                     .min();
+            time = match next_finish {
+                Some(finish) => finish,
+                None => break,
+            };
+
+            for worker in &mut workers {
+                if let Some((task, finish)) = *worker {
+                    if finish == time {
+                        self.release(task, &mut unmet, &priorities,
+                                     &mut ready);
+                        *worker = None;
+                    }
+                }
+            }
+        }
+
+        Ok(Schedule { policy: policy.name().to_string(), num_workers,
+                      assignments, makespan: time,
+                      critical_path: critical_path.iter()
+                                                  .map(|t| t.to_string())
+                                                  .collect(),
+                      critical_length })
+    }
+
+    /// Makespan under each of `policies`, in the same order.
+    pub fn compare_policies<F>(&self, num_workers: usize, duration: F,
+                               policies: &[Box<dyn Policy>])
+            -> Result<Vec<(String, u32)>, Vec<GraphError>>
+            where F: Fn(&str) -> u32 {
+        let mut makespans = Vec::new();
+        for policy in policies {
+            let schedule = self.schedule_with(num_workers, &duration,
+                                              policy.as_ref())?;
+            makespans.push((schedule.policy, schedule.makespan));
+        }
+        Ok(makespans)
+    }
+}
@@ -1,6 +1,7 @@
 use std::io::prelude::*;
 use std::fs::File;
-use std::slice::Iter;
+use std::env;
+use std::mem;
 
 #[cfg(test)]
 mod tests {
@@ -9,58 +10,298 @@ mod tests {
     #[test]
     fn test_both_halves() {
         let input = String::from("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2\n");
-        let tokens = parse_input(input);
-        assert_eq!(metadata_sum(&mut tokens.iter()), 138);
-        assert_eq!(node_value(&mut tokens.iter()), 66);
+        let tree = parse_input(input).unwrap();
+        assert_eq!(metadata_sum(&tree), 138);
+        assert_eq!(node_value(&tree), 66);
     }
+
+    #[test]
+    fn test_malformed() {
+        let parse = |input: &str| parse_input(String::from(input));
+        assert_eq!(parse("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1").unwrap_err(),
+                   ParseError::Truncated);
+        assert_eq!(parse("").unwrap_err(), ParseError::Truncated);
+        assert_eq!(parse("0 1 7 0 1 7").unwrap_err(),
+                   ParseError::TrailingTokens(3));
+        assert_eq!(parse("0 1 x").unwrap_err(),
+                   ParseError::InvalidToken(String::from("x")));
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let depth = 200_000;
+        let mut input = "1 1 ".repeat(depth);
+        input.push_str("0 1 5");
+        input.push_str(&" 1".repeat(depth));
+        let tree = parse_input(input).unwrap();
+        assert_eq!(metadata_sum(&tree), 5 + depth as u32);
+        assert_eq!(node_value(&tree), 5);
+        assert_eq!(tree_depth(&tree), depth + 1);
+        assert_eq!(path_to(&tree, depth), Some(vec![0; depth]));
+    }
+
+    #[test]
+    fn test_queries() {
+        let input = String::from("2 3 0 3 10 11 12 1 1 0 1 99 2 1 1 2");
+        let tree = parse_input(input.clone()).unwrap();
+        assert_eq!(node_count(&tree), 4);
+        assert_eq!(tree_depth(&tree), 3);
+        assert_eq!(path_to(&tree, 3), Some(vec![1, 0]));
+        assert_eq!(path_to(&tree, 4), None);
+        assert_eq!(subtree_value(&tree, 1), Some(33));
+        assert_eq!(subtree_value(&tree, 2), Some(0));
+        assert_eq!(pretty_print(&tree),
+                   "0: [1 1 2] value 66\n  \
+                      1: [10 11 12] value 33\n  \
+                      2: [2] value 0\n    \
+                        3: [99] value 99\n");
+        assert!(to_dot(&tree).contains("    n2 -> n3;\n"));
+        assert_eq!(serialise(&tree), input);
+    }
+
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, range: u32) -> u32 {
+            self.0 = self.0.wrapping_mul(6364136223846793005)
+                           .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % u64::from(range)) as u32
+        }
+    }
+
+    fn random_tree(rng: &mut Lcg, depth: u32) -> Node {
+        let num_children = if depth == 0 { 0 } else { rng.below(4) };
+        let children = (0..num_children).map(|_| random_tree(rng, depth - 1))
+                                        .collect();
+        let num_entries = rng.below(4);
+        let metadata = (0..num_entries).map(|_| rng.below(100)).collect();
+        Node { children, metadata }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // Fixed seed, so failures are reproducible
+        let mut rng = Lcg(2018);
+        for _ in 0..500 {
+            let depth = rng.below(6);
+            let tree = random_tree(&mut rng, depth);
+            let serialised = serialise(&tree);
+            let parsed = parse_input(serialised.clone()).unwrap();
+            assert_eq!(parsed, tree);
+            assert_eq!(serialise(&parsed), serialised);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct Node {
+    children: Vec<Node>,
+    metadata: Vec<u32>,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        // Dropping children recursively overflows the stack on deep trees
+        let mut stack = mem::take(&mut self.children);
+        while let Some(mut node) = stack.pop() {
+            stack.append(&mut node.children);
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParseError {
+    InvalidToken(String),
+    Truncated,
+    TrailingTokens(usize),
+}
+
+fn build_tree(tokens: &[u32]) -> Result<Node, ParseError> {
+    let mut tokens = tokens.iter();
+
+    // Nodes still being read, with the number of children left to read
+    // and the number of metadata entries following them
+    let mut stack: Vec<(u32, u32, Vec<Node>)> = Vec::new();
+    loop {
+        let unread_children = match stack.last_mut() {
+            Some(frame) if frame.0 > 0 => {
+                frame.0 -= 1;
+                true
+            },
+            Some(_) => false,
+            None => true,
+        };
+
+        if unread_children {
+            let child_nodes = *tokens.next().ok_or(ParseError::Truncated)?;
+            let metadata_entries = *tokens.next().ok_or(ParseError::Truncated)?;
+            stack.push((child_nodes, metadata_entries, Vec::new()));
+            continue;
+        }
+
+        let (_, metadata_entries, children) = stack.pop().unwrap();
+        let mut metadata = Vec::new();
+        for _ in 0..metadata_entries {
+            metadata.push(*tokens.next().ok_or(ParseError::Truncated)?);
+        }
+
+        let node = Node { children, metadata };
+        match stack.last_mut() {
+            Some(parent) => parent.2.push(node),
+            None => {
+                let trailing = tokens.count();
+                if trailing > 0 {
+                    return Err(ParseError::TrailingTokens(trailing));
+                }
+                return Ok(node);
+            },
+        }
+    }
+}
+
+fn metadata_sum(root: &Node) -> u32 {
+    let mut sum = 0;
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        sum += node.metadata.iter().sum::<u32>();
+        stack.extend(node.children.iter());
+    }
+    sum
+}
+
+struct Visit<'a> {
+    node: &'a Node,
+    parent: Option<usize>,
+    position: usize,
+    depth: usize,
+}
+
+fn preorder(root: &Node) -> Vec<Visit<'_>> {
+    // Nodes are numbered in the order their headers appear in the input
+    let mut visits = Vec::new();
+    let mut stack = vec![Visit { node: root, parent: None, position: 0,
+                                 depth: 0 }];
+    while let Some(visit) = stack.pop() {
+        let index = visits.len();
+        for (position, child) in visit.node.children.iter().enumerate().rev() {
+            stack.push(Visit { node: child, parent: Some(index), position,
+                               depth: visit.depth + 1 });
+        }
+        visits.push(visit);
+    }
+    visits
+}
+
+fn node_values(root: &Node) -> Vec<u32> {
+    // Children always come after their parent, so walking backwards
+    // every child's value is known before its parent's
+    let visits = preorder(root);
+    let mut children = vec![Vec::new(); visits.len()];
+    for (index, visit) in visits.iter().enumerate() {
+        if let Some(parent) = visit.parent {
+            children[parent].push(index);
+        }
+    }
+
+    let mut values = vec![0; visits.len()];
+    for (index, visit) in visits.iter().enumerate().rev() {
+        let node = visit.node;
+        values[index] = if node.children.is_empty() {
+            node.metadata.iter().sum()
+        } else {
+            node.metadata.iter()
+                .filter_map(|&entry| children[index].get((entry as usize).wrapping_sub(1)))
+                .map(|&child| values[child])
+                .sum()
+        };
+    }
+    values
+}
+
+fn node_value(root: &Node) -> u32 {
+    node_values(root)[0]
 }
 
-fn metadata_sum(tokens: &mut Iter<u32>) -> u32 {
-	let mut sum = 0;
-	let child_nodes = *tokens.next().unwrap();
-	let metadata_entries = *tokens.next().unwrap();
+fn node_count(root: &Node) -> usize {
+    preorder(root).len()
+}
 
-	for _ in 0..child_nodes {
-		sum += metadata_sum(tokens);
-	}
+fn tree_depth(root: &Node) -> usize {
+    preorder(root).iter().map(|visit| visit.depth + 1).max().unwrap()
+}
 
-	for _ in 0..metadata_entries {
-		sum += tokens.next().unwrap();
-	}
-	sum
+fn path_to(root: &Node, index: usize) -> Option<Vec<usize>> {
+    // Child positions leading from the root to the node
+    let visits = preorder(root);
+    let mut visit = visits.get(index)?;
+    let mut path = Vec::new();
+    while let Some(parent) = visit.parent {
+        path.push(visit.position);
+        visit = &visits[parent];
+    }
+    path.reverse();
+    Some(path)
 }
 
-fn node_value(tokens: &mut Iter<u32>) -> u32 {
-	let mut sum = 0;
-	let child_nodes = *tokens.next().unwrap();
-	let metadata_entries = *tokens.next().unwrap();
+fn subtree_value(root: &Node, index: usize) -> Option<u32> {
+    node_values(root).get(index).cloned()
+}
 
-	let mut child_values = Vec::new();
-	for _ in 0..child_nodes {
-		child_values.push(node_value(tokens));
-	}
+fn join_metadata(node: &Node) -> String {
+    node.metadata.iter()
+        .map(|entry| entry.to_string())
+        .collect::<Vec<String>>()
+        .join(" ")
+}
 
-	if child_nodes == 0 {
-		for _ in 0..metadata_entries {
-			sum += tokens.next().unwrap();
-		}
-	} else {
-		for _ in 0..metadata_entries {
-			let entry = *tokens.next().unwrap() as usize;
-			if entry > child_values.len() {
-				continue;
-			}
+fn pretty_print(root: &Node) -> String {
+    let values = node_values(root);
+    let mut tree = String::new();
+    for (index, visit) in preorder(root).iter().enumerate() {
+        tree.push_str(&format!("{}{}: [{}] value {}\n", "  ".repeat(visit.depth),
+                               index, join_metadata(visit.node), values[index]));
+    }
+    tree
+}
 
-			sum += child_values[entry-1];
-		}
-	}
-	sum
+fn to_dot(root: &Node) -> String {
+    let values = node_values(root);
+    let mut dot = String::from("digraph license {\n");
+    for (index, visit) in preorder(root).iter().enumerate() {
+        dot.push_str(&format!("    n{} [label=\"{}\\n[{}]\\nvalue {}\"];\n", index,
+                              index, join_metadata(visit.node), values[index]));
+        if let Some(parent) = visit.parent {
+            dot.push_str(&format!("    n{} -> n{};\n", parent, index));
+        }
+    }
+    dot.push_str("}\n");
+    dot
 }
 
-fn parse_input(input: String) -> Vec<u32> {
-	input.trim().split(" ")
-                .map(|x| x.parse::<u32>().unwrap())
-                .collect()
+fn serialise(root: &Node) -> String {
+    // Headers are written on the way down, metadata on the way back up
+    let mut tokens = Vec::new();
+    let mut stack = vec![(root, false)];
+    while let Some((node, children_done)) = stack.pop() {
+        if children_done {
+            tokens.extend(node.metadata.iter().map(|entry| entry.to_string()));
+            continue;
+        }
+
+        tokens.push(node.children.len().to_string());
+        tokens.push(node.metadata.len().to_string());
+        stack.push((node, true));
+        stack.extend(node.children.iter().rev().map(|child| (child, false)));
+    }
+    tokens.join(" ")
+}
+
+fn parse_input(input: String) -> Result<Node, ParseError> {
+    let tokens = input.split_whitespace()
+                      .map(|x| x.parse::<u32>()
+                                .map_err(|_| ParseError::InvalidToken(x.to_string())))
+                      .collect::<Result<Vec<u32>, ParseError>>()?;
+    build_tree(&tokens)
 }
 
 fn main() {
@@ -68,7 +309,28 @@ fn main() {
     let mut f = File::open("input").expect("Failed to open input.");
     f.read_to_string(&mut input).expect("Failed to read input.");
 
-    let tokens = parse_input(input);
-    println!("Sum of metadata: {}", metadata_sum(&mut tokens.iter()));
-    println!("Value of first node: {}", node_value(&mut tokens.iter()));
+    let tree = parse_input(input).expect("Failed to parse input.");
+    println!("Sum of metadata: {}", metadata_sum(&tree));
+    println!("Value of first node: {}", node_value(&tree));
+    println!("{} nodes, {} levels deep", node_count(&tree), tree_depth(&tree));
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tree" => print!("{}", pretty_print(&tree)),
+            "--dot" => print!("{}", to_dot(&tree)),
+            "--serialise" => println!("{}", serialise(&tree)),
+            "--node" => {
+                let index = args.next()
+                                .and_then(|index| index.parse().ok())
+                                .expect("--node takes a node number.");
+                match (path_to(&tree, index), subtree_value(&tree, index)) {
+                    (Some(path), Some(value)) =>
+                        println!("Node {}: path {:?}, value {}", index, path, value),
+                    _ => println!("Node {} doesn't exist", index),
+                }
+            },
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
 }
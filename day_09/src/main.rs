@@ -1,5 +1,3 @@
-use std::collections::VecDeque;
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -13,50 +11,156 @@ mod tests {
         assert_eq!(high_score(21, 6111), 54718);
         assert_eq!(high_score(30, 5807), 37305);
     }
-}
 
-fn rotate_ccw(ring: &mut VecDeque<u32>, positions: u32) {
-    for _ in 0..positions {
-        let val = ring.pop_back().unwrap();
-        ring.push_front(val);
+    #[test]
+    fn test_ring() {
+        let mut ring = Ring::new(25);
+        for i in 1..26 {
+            play_marble(&mut ring, i, &AOC_RULES);
+        }
+        assert_eq!(ring.current, 25);
+
+        let mut order = vec![0];
+        while ring.next[*order.last().unwrap() as usize] != 0 {
+            order.push(ring.next[*order.last().unwrap() as usize]);
+        }
+        assert_eq!(order, vec![0, 16, 8, 17, 4, 18, 19, 2, 24, 20, 25, 10,
+                               21, 5, 22, 11, 1, 12, 6, 13, 3, 14, 7, 15]);
+    }
+
+    #[test]
+    fn test_game_result() {
+        let result = play_game(9, 25, &AOC_RULES);
+        assert_eq!(result.winner, 4);
+        assert_eq!(result.scores, vec![0, 0, 0, 0, 32, 0, 0, 0, 0]);
+        assert_eq!(result.history, vec![(23, 4, 32)]);
+
+        let rules = Rules { special_multiple: 4, removal_offset: 2,
+                            insertion_offset: 1 };
+        let result = play_game(2, 8, &rules);
+        assert_eq!(result.scores, vec![0, 20]);
+        assert_eq!(result.winner, 1);
+        assert_eq!(result.history, vec![(4, 1, 6), (8, 1, 14)]);
     }
 }
 
-fn rotate_cw(ring: &mut VecDeque<u32>, positions: u32) {
-    for _ in 0..positions {
-        let val = ring.pop_front().unwrap();
-        ring.push_back(val);
+/// Every `special_multiple`th marble is kept, along with the marble
+/// `removal_offset` positions counter-clockwise of the current one. Other
+/// marbles are placed `insertion_offset` positions clockwise of it.
+#[derive(Debug, Copy, Clone)]
+struct Rules {
+    special_multiple: u32,
+    removal_offset: u32,
+    insertion_offset: u32,
+}
+
+const AOC_RULES: Rules = Rules { special_multiple: 23, removal_offset: 7,
+                                 insertion_offset: 1 };
+
+/// Final scores, and every score as `(marble, player, points)`.
+#[derive(Debug, Clone)]
+struct GameResult {
+    scores: Vec<u64>,
+    winner: usize,
+    history: Vec<(u32, usize, u64)>,
+}
+
+/// Circle of marbles as a doubly linked list, indexed by marble value.
+#[derive(Debug, Clone)]
+struct Ring {
+    next: Vec<u32>,
+    prev: Vec<u32>,
+    current: u32,
+}
+
+impl Ring {
+    fn new(last_marble: u32) -> Ring {
+        // Marble 0 starts out as its own neighbour
+        Ring { next: vec![0; last_marble as usize + 1],
+               prev: vec![0; last_marble as usize + 1],
+               current: 0 }
+    }
+
+    fn rotate_ccw(&mut self, positions: u32) {
+        for _ in 0..positions {
+            self.current = self.prev[self.current as usize];
+        }
+    }
+
+    fn rotate_cw(&mut self, positions: u32) {
+        for _ in 0..positions {
+            self.current = self.next[self.current as usize];
+        }
+    }
+
+    fn insert_after_current(&mut self, value: u32) {
+        let before = self.current;
+        let after = self.next[before as usize];
+        self.next[before as usize] = value;
+        self.prev[after as usize] = value;
+        self.next[value as usize] = after;
+        self.prev[value as usize] = before;
+        self.current = value;
+    }
+
+    fn remove_current(&mut self) -> u32 {
+        let removed = self.current;
+        let before = self.prev[removed as usize];
+        let after = self.next[removed as usize];
+        self.next[before as usize] = after;
+        self.prev[after as usize] = before;
+        self.current = after;
+        removed
     }
 }
 
-fn play_marble(ring: &mut VecDeque<u32>, value: u32) -> u32 {
-    if value % 23 == 0 {
-        rotate_ccw(ring, 7);
-        let score = value + ring.pop_back().unwrap();
-        rotate_cw(ring, 1);
-        score
+fn play_marble(ring: &mut Ring, value: u32, rules: &Rules) -> u64 {
+    if value.is_multiple_of(rules.special_multiple) {
+        ring.rotate_ccw(rules.removal_offset);
+        u64::from(value) + u64::from(ring.remove_current())
     } else {
-        rotate_cw(ring, 1);
-        ring.push_back(value);
+        ring.rotate_cw(rules.insertion_offset);
+        ring.insert_after_current(value);
         0
     }
 }
 
-fn high_score(num_players: u32, last_marble: u32) -> u32 {
+fn play_game(num_players: u32, last_marble: u32, rules: &Rules) -> GameResult {
+    // With every marble special the circle would run empty
+    assert!(rules.special_multiple > 1, "Special multiple must exceed 1");
+
     let mut scores = vec![0; num_players as usize];
-    let mut ring = VecDeque::new();
-    ring.push_back(0);
+    let mut history = Vec::new();
+    let mut ring = Ring::new(last_marble);
 
     let mut current_player = 0;
     for i in 1..last_marble+1 {
-
-        scores[current_player] += play_marble(&mut ring, i);
+        let points = play_marble(&mut ring, i, rules);
+        if points > 0 {
+            scores[current_player] += points;
+            history.push((i, current_player, points));
+        }
         current_player = (current_player+1) % num_players as usize;
     }
-    *scores.iter().max().unwrap()
+
+    let mut winner = 0;
+    for (player, &score) in scores.iter().enumerate() {
+        if score > scores[winner] {
+            winner = player;
+        }
+    }
+    GameResult { scores, winner, history }
+}
+
+fn high_score(num_players: u32, last_marble: u32) -> u64 {
+    let result = play_game(num_players, last_marble, &AOC_RULES);
+    result.scores[result.winner]
 }
 
 fn main() {
     println!("In the reasonable case, high score is {}", high_score(424, 71482));
-    println!("In the unreasonable case, high score is {}", high_score(424, 71482*100));
+
+    let result = play_game(424, 71482*100, &AOC_RULES);
+    println!("In the unreasonable case, high score is {} by player {} after {} scores",
+             result.scores[result.winner], result.winner + 1, result.history.len());
 }
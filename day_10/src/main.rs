@@ -2,13 +2,6 @@ use std::io::prelude::*;
 use std::fs::File;
 use std::cmp;
 
-struct Point {
-    x: isize,
-    y: isize,
-    vx: isize,
-    vy: isize,
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -48,39 +41,155 @@ mod tests {
     #[test]
     fn test_first_half() {
         let input = String::from(TEST_INPUT);
-        let mut points = parse_input(input);
-        assert_eq!(find_min_area(&mut points, 2), 3);
+        let mut points = parse_input(input).unwrap();
+        assert_eq!(find_min_area(&mut points), 3);
         print_points(&points);
     }
 
+    #[test]
+    fn test_read_message() {
+        // Spell out a message in the large font and scatter it
+        let message = "ZABCEFGHJKLNPRX";
+        let mut points = Vec::new();
+        for (i, letter) in message.chars().enumerate() {
+            let glyph = FONT.iter().find(|&&(c, _)| c == letter).unwrap().1;
+            for (y, row) in glyph.iter().enumerate() {
+                for (x, _) in row.chars().enumerate().filter(|&(_, c)| c == '#') {
+                    let vx = (x as isize + y as isize) % 5 - 2;
+                    let vy = (i as isize + x as isize) % 3 - 1;
+                    points.push(Point { x: (8*i + x) as isize - 517*vx,
+                                        y: y as isize - 517*vy, vx, vy });
+                }
+            }
+        }
+
+        assert_eq!(find_min_area(&mut points), 517);
+        assert_eq!(read_message(&points), Some(String::from(message)));
+
+        let points = parse_input(String::from(TEST_INPUT)).unwrap();
+        assert_eq!(read_message(&points), None);
+    }
+
+    #[test]
+    fn test_parse_input() {
+        let input = String::from("position=<-123456789, 42> velocity=<7,-1000>\n\
+                                  \n\
+                                  position=< 0 , 0 >  velocity=< -1 ,  1 >");
+        let points = parse_input(input).unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!((points[0].x, points[0].y, points[0].vx, points[0].vy),
+                   (-123456789, 42, 7, -1000));
+        assert_eq!((points[1].vx, points[1].vy), (-1, 1));
+
+        for bad in &["position=<1, 2> velocity=<3>",
+                     "position=<1, 2, 3> velocity=<3, 4>",
+                     "velocity=<3, 4> position=<1, 2>",
+                     "position=<1, x> velocity=<3, 4>"] {
+            let input = format!("position=<1, 2> velocity=<3, 4>\n{}", bad);
+            assert!(parse_input(input).unwrap_err().starts_with("Line 2:"));
+        }
+        assert!(parse_input(String::from("")).is_err());
+        assert!(parse_input(String::from("\n  \n")).is_err());
+    }
+
     #[test]
     fn test_simulate_step() {
         let input = String::from(TEST_INPUT);
-        let mut points = parse_input(input);
-        let old_area = bb_area(&points);
-        for steps in vec![1024, 100, 300, 255, 10] {
+        let mut points = parse_input(input).unwrap();
+        let old_span = bb_span_at(&points, 0);
+        for steps in [1024, 100, 300, 255, 10] {
             simulate(&mut points, steps);
             simulate(&mut points, -steps);
-            assert_eq!(old_area, bb_area(&points));
+            assert_eq!(old_span, bb_span_at(&points, 0));
         }
     }
 }
 
-fn parse_input(input: String) -> Vec<Point> {
+#[derive(Debug, Copy, Clone)]
+struct Point {
+    x: isize,
+    y: isize,
+    vx: isize,
+    vy: isize,
+}
+
+// The large AoC font, letters are six wide and ten tall with two
+// blank columns in between
+const GLYPH_WIDTH: isize = 6;
+const GLYPH_HEIGHT: isize = 10;
+const GLYPH_SPACING: isize = 2;
+const FONT: [(char, [&str; 10]); 15] = [
+    ('A', ["..##..", ".#..#.", "#....#", "#....#", "#....#",
+           "######", "#....#", "#....#", "#....#", "#....#"]),
+    ('B', ["#####.", "#....#", "#....#", "#....#", "#####.",
+           "#....#", "#....#", "#....#", "#....#", "#####."]),
+    ('C', [".####.", "#....#", "#.....", "#.....", "#.....",
+           "#.....", "#.....", "#.....", "#....#", ".####."]),
+    ('E', ["######", "#.....", "#.....", "#.....", "#####.",
+           "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('F', ["######", "#.....", "#.....", "#.....", "#####.",
+           "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('G', [".####.", "#....#", "#.....", "#.....", "#.....",
+           "#..###", "#....#", "#....#", "#...##", ".###.#"]),
+    ('H', ["#....#", "#....#", "#....#", "#....#", "######",
+           "#....#", "#....#", "#....#", "#....#", "#....#"]),
+    ('J', ["...###", "....#.", "....#.", "....#.", "....#.",
+           "....#.", "....#.", "#...#.", "#...#.", ".###.."]),
+    ('K', ["#....#", "#...#.", "#..#..", "#.#...", "##....",
+           "##....", "#.#...", "#..#..", "#...#.", "#....#"]),
+    ('L', ["#.....", "#.....", "#.....", "#.....", "#.....",
+           "#.....", "#.....", "#.....", "#.....", "######"]),
+    ('N', ["#....#", "##...#", "##...#", "#.#..#", "#.#..#",
+           "#..#.#", "#..#.#", "#...##", "#...##", "#....#"]),
+    ('P', ["#####.", "#....#", "#....#", "#....#", "#####.",
+           "#.....", "#.....", "#.....", "#.....", "#....."]),
+    ('R', ["#####.", "#....#", "#....#", "#....#", "#####.",
+           "#..#..", "#...#.", "#...#.", "#....#", "#....#"]),
+    ('X', ["#....#", "#....#", ".#..#.", ".#..#.", "..##..",
+           "..##..", ".#..#.", ".#..#.", "#....#", "#....#"]),
+    ('Z', ["######", ".....#", ".....#", "....#.", "...#..",
+           "..#...", ".#....", "#.....", "#.....", "######"]),
+];
+
+fn parse_pair(field: &str) -> Option<(isize, isize)> {
+    // "<x,y>" with whitespace already removed
+    let field = field.strip_prefix('<')?.strip_suffix('>')?;
+    let mut values = field.split(',').map(|v| v.parse::<isize>());
+    match (values.next(), values.next(), values.next()) {
+        (Some(Ok(a)), Some(Ok(b)), None) => Some((a, b)),
+        _ => None,
+    }
+}
+
+fn parse_line(line: &str) -> Option<Point> {
+    let line: String = line.split_whitespace().collect();
+    let rest = line.strip_prefix("position=")?;
+    let split = rest.find("velocity=")?;
+    let (x, y) = parse_pair(&rest[..split])?;
+    let (vx, vy) = parse_pair(&rest[split + "velocity=".len()..])?;
+    Some(Point { x, y, vx, vy })
+}
+
+fn parse_input(input: String) -> Result<Vec<Point>, String> {
     let mut points = Vec::new();
-    for line in input.lines() {
-        let mut pos = line[10..24].split(",")
-                                  .map(|v| v.trim().parse::<isize>().unwrap());
-        let mut vel = line[36..42].split(",")
-                                  .map(|v| v.trim().parse::<isize>().unwrap());
-        let point = Point {x: pos.next().unwrap(), y: pos.next().unwrap(),
-                           vx: vel.next().unwrap(), vy: vel.next().unwrap()};
-        points.push(point);
-    }
-    points
+    for (line_num, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_line(line) {
+            Some(point) => points.push(point),
+            None => return Err(format!("Line {}: expected 'position=<x, y> \
+                                        velocity=<vx, vy>', found '{}'",
+                                       line_num + 1, line)),
+        }
+    }
+    if points.is_empty() {
+        return Err(String::from("No points in input"));
+    }
+    Ok(points)
 }
 
-fn bounding_box(points: &Vec<Point>) -> (isize, isize, isize, isize) {
+fn bounding_box(points: &[Point]) -> (isize, isize, isize, isize) {
     let mut min_x = points[0].x;
     let mut max_x = points[0].x;
     let mut min_y = points[0].y;
@@ -94,61 +203,103 @@ fn bounding_box(points: &Vec<Point>) -> (isize, isize, isize, isize) {
     (min_x, max_x, min_y, max_y)
 }
 
-fn bb_area(points: &Vec<Point>) -> f64 {
-    let (min_x, max_x, min_y, max_y) = bounding_box(points);
-    (max_x - min_x) as f64 * (max_y - min_y) as f64
+/// Width plus height of the bounding box at `time`.
+fn bb_span_at(points: &[Point], time: isize) -> isize {
+    let (mut min_x, mut max_x) = (isize::MAX, isize::MIN);
+    let (mut min_y, mut max_y) = (isize::MAX, isize::MIN);
+    for point in points {
+        let x = point.x + point.vx*time;
+        let y = point.y + point.vy*time;
+        min_x = cmp::min(min_x, x);
+        max_x = cmp::max(max_x, x);
+        min_y = cmp::min(min_y, y);
+        max_y = cmp::max(max_y, y);
+    }
+    (max_x - min_x) + (max_y - min_y)
 }
 
-fn simulate(points: &mut Vec<Point>, steps: isize) {
+fn simulate(points: &mut [Point], steps: isize) {
     for point in points {
         point.x += point.vx*steps;
         point.y += point.vy*steps;
     }
 }
 
-fn find_min_area(points: &mut Vec<Point>, initial_step_size: isize) -> isize {
-    let mut time = 0;
-    let mut step = initial_step_size;
-    let mut max_reached = false;
-    loop {
-        time += step;
-        simulate(points, step+1);
-        let area_fwd = bb_area(points);
-        simulate(points, -2);
-        let area_back = bb_area(points);
-        simulate(points, 1);
-        let area_at = bb_area(points);
-        match (area_at <= area_back, area_at <= area_fwd) {
-            (true, true) => break, // local minima
-            (true, false) => if max_reached { step = step.abs()/2; }, // minima towards the future
-            (false, true) => { max_reached = true;  step = -step.abs()/2; }, // minima towards the past
-            (false, false) => panic!("Reached a local maxima"),
+fn find_min_area(points: &mut [Point]) -> isize {
+    // The width and height of the bounding box are each the largest minus
+    // the smallest of linear functions of time, so both are convex. Their
+    // product, the area, can have two separate minima, but their sum has a
+    // single one (or a flat stretch). Bracket it by doubling, then ternary
+    // search for it.
+    let span = |time| bb_span_at(points, time);
+    let mut end = 1;
+    while span(2*end) < span(end) {
+        end *= 2;
+    }
+
+    let (mut low, mut high) = (0, 2*end);
+    while high - low > 2 {
+        let third = (high - low) / 3;
+        let (early, late) = (low + third, high - third);
+        match span(early).cmp(&span(late)) {
+            cmp::Ordering::Less => high = late - 1,
+            cmp::Ordering::Greater => low = early + 1,
+            cmp::Ordering::Equal => { low = early; high = late; },
         }
-        if step == 1 {
-            break;
+    }
+
+    let mut time = low;
+    for t in low..high+1 {
+        if span(t) < span(time) {
+            time = t;
         }
     }
+    simulate(points, time);
     time
 }
 
-fn print_points(points: &Vec<Point>) {
+fn render(points: &[Point]) -> Vec<Vec<bool>> {
     let (min_x, max_x, min_y, max_y) = bounding_box(points);
-    let sky_height = max_y - min_y + 1;
-    let sky_width = max_x - min_x + 1;
-    let mut sky = Vec::new();
-    for _ in 0..sky_height {
-        sky.append(&mut vec!['.'; sky_width as usize]);
-        sky.push('\n');
+    let mut sky = vec![vec![false; (max_x - min_x + 1) as usize];
+                       (max_y - min_y + 1) as usize];
+    for point in points {
+        sky[(point.y - min_y) as usize][(point.x - min_x) as usize] = true;
     }
+    sky
+}
 
-    for point in points {
-        let x = point.x - min_x;
-        let y = point.y - min_y;
-        let point = x + (sky_width+1)*y;
-        sky[point as usize] = '#';
+fn read_message(points: &[Point]) -> Option<String> {
+    // Unknown letters are read as '?', None if the points don't form a
+    // line of letters
+    let sky = render(points);
+    let width = sky[0].len() as isize;
+    let pitch = GLYPH_WIDTH + GLYPH_SPACING;
+    if sky.len() as isize != GLYPH_HEIGHT || (width + GLYPH_SPACING) % pitch != 0 {
+        return None;
     }
 
-    let sky: String = sky.into_iter().collect();
+    let mut message = String::new();
+    for left in (0..width).step_by(pitch as usize) {
+        let left = left as usize;
+        let glyph: Vec<String> = sky.iter()
+            .map(|row| row[left..left + GLYPH_WIDTH as usize].iter()
+                          .map(|&lit| if lit { '#' } else { '.' })
+                          .collect())
+            .collect();
+        let letter = FONT.iter()
+                         .find(|(_, rows)| rows.iter().eq(glyph.iter()))
+                         .map_or('?', |&(letter, _)| letter);
+        message.push(letter);
+    }
+    Some(message)
+}
+
+fn print_points(points: &[Point]) {
+    let mut sky = String::new();
+    for row in render(points) {
+        sky.extend(row.into_iter().map(|lit| if lit { '#' } else { '.' }));
+        sky.push('\n');
+    }
     println!("{}", sky);
 }
 
@@ -157,7 +308,10 @@ fn main() {
     let mut f = File::open("input").expect("Failed to open input.");
     f.read_to_string(&mut input).expect("Failed to read input.");
 
-    let mut points = parse_input(input);
-    println!("Stop time: {}", find_min_area(&mut points, 2048));
-    print_points(&points);
+    let mut points = parse_input(input).expect("Failed to parse input.");
+    println!("Stop time: {}", find_min_area(&mut points));
+    match read_message(&points) {
+        Some(message) => println!("Message: {}", message),
+        None => print_points(&points),
+    }
 }
@@ -1,4 +1,8 @@
 use std::cmp;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::ops::RangeInclusive;
+use std::thread;
 
 #[cfg(test)]
 mod tests {
@@ -6,16 +10,58 @@ mod tests {
 
     #[test]
     fn test_all() {
-        let mut grid = create_grid(18);
-        integrate(&mut grid);
-        assert_eq!(find_max_at_size(&grid, 3), (29, 33, 45));
-        assert_eq!(find_max(&grid), (90, 269, 16));
+        let grid = PowerGrid::new(300, 300, 18);
+        assert_eq!(grid.find_max_at_size(3), (29, 33, 45));
+        assert_eq!(grid.find_max(), (90, 269, 16));
 
 
-        let mut grid = create_grid(42);
-        integrate(&mut grid);
-        assert_eq!(find_max_at_size(&grid, 3), (30, 21, 61));
-        assert_eq!(find_max(&grid), (232, 251, 12));
+        let grid = PowerGrid::new(300, 300, 42);
+        assert_eq!(grid.find_max_at_size(3), (30, 21, 61));
+        assert_eq!(grid.find_max(), (232, 251, 12));
+    }
+
+    #[test]
+    fn test_rectangles() {
+        // Only the column x = 3 is positive, increasingly so further down
+        let grid = PowerGrid::from_fn(5, 8, |x, y| if x == 3 { y as i32 } else { -1 });
+        assert_eq!(grid.sum_of_area(3, 2, 1, 3), 2 + 3 + 4);
+        assert_eq!(grid.sum_of_area(1, 1, 5, 8), 36 - 4*8);
+        assert_eq!(grid.find_max_rect(1..=5, 1..=8),
+                   Some(Rect { power: 36, x: 3, y: 1, width: 1, height: 8 }));
+        assert_eq!(grid.find_max_rect(2..=3, 1..=2),
+                   Some(Rect { power: 13, x: 2, y: 7, width: 2, height: 2 }));
+        assert_eq!(grid.find_max_rect(6..=9, 1..=8), None);
+    }
+
+    #[test]
+    fn test_top_squares() {
+        let grid = PowerGrid::new(45, 38, 18);
+        let mut all = Vec::new();
+        for size in 1..39 {
+            for y in 1..38-size+2 {
+                for x in 1..45-size+2 {
+                    let power = grid.sum_of_area(x, y, size, size);
+                    all.push(Rect { power, x, y, width: size, height: size });
+                }
+            }
+        }
+        all.sort_by_key(|square| Reverse(rank(square)));
+
+        for &threads in &[1, 4] {
+            assert_eq!(grid.top_squares(1..=38, 20, threads), &all[..20]);
+            assert_eq!(grid.top_squares(1..=38, 1, threads), &all[..1]);
+        }
+        let mut sized: Vec<Rect> = all.into_iter()
+                                      .filter(|s| s.width >= 5 && s.width <= 9)
+                                      .collect();
+        sized.truncate(7);
+        assert_eq!(grid.top_squares(5..=9, 7, 3), sized);
+
+        let grid = PowerGrid::new(300, 300, 42);
+        let top = grid.top_squares(1..=300, 3, 4);
+        assert_eq!((top[0].power, top[0].x, top[0].y, top[0].width),
+                   (119, 232, 251, 12));
+        assert!(top[1].power <= 119 && top[2].power <= top[1].power);
     }
 
     #[test]
@@ -27,12 +73,11 @@ mod tests {
 
     #[test]
     fn test_integrate() {
-        let mut grid = vec![vec![1; 300]; 300];
-        integrate(&mut grid);
+        let grid = PowerGrid::from_fn(300, 300, |_, _| 1);
 
-        for y in 0..300 {
-            for x in 0..300 {
-                assert_eq!(grid[y][x], ((y+1)*(x+1)) as i32);
+        for y in 1..301 {
+            for x in 1..301 {
+                assert_eq!(grid.sum_of_area(1, 1, x, y), (y*x) as i32);
             }
         }
 
@@ -42,71 +87,218 @@ mod tests {
 fn cell_power(x: i32, y: i32, serial: i32) -> i32 {
     let rack_id = x + 10;
     let mut power = rack_id * y + serial;
-    power = power * rack_id;
+    power *= rack_id;
     power = (power % 1000) / 100;
     power - 5
 }
 
-fn create_grid(serial: i32) -> Vec<Vec<i32>> {
-    let mut grid = vec![vec![0; 300]; 300];
-    for y in 0..300 {
-        for x in 0..300 {
-            grid[y][x] = cell_power(x as i32 + 1, y as i32 + 1, serial);
-        }
+/// A rectangle of cells, with its top-left corner at (x, y).
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+struct Rect {
+    power: i32,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+// Orders squares by power, then smaller and earlier squares first
+type Rank = (i32, Reverse<usize>, Reverse<usize>, Reverse<usize>);
+
+fn rank(square: &Rect) -> Rank {
+    (square.power, Reverse(square.width), Reverse(square.y), Reverse(square.x))
+}
+
+fn unrank(rank: Rank) -> Rect {
+    let (power, Reverse(size), Reverse(y), Reverse(x)) = rank;
+    Rect { power, x, y, width: size, height: size }
+}
+
+fn push_bounded(top: &mut BinaryHeap<Reverse<Rank>>, square: &Rect, k: usize) {
+    // Keeps the k best, with the worst of them on top of the heap
+    let rank = rank(square);
+    if top.len() < k {
+        top.push(Reverse(rank));
+    } else if top.peek().is_some_and(|&Reverse(worst)| rank > worst) {
+        top.pop();
+        top.push(Reverse(rank));
     }
-    grid
 }
 
-fn integrate(grid: &mut Vec<Vec<i32>>) {
-    // Builds summed-area table out of grid
-    for y in 0..300 {
-        for x in 0..300 {
-            grid[y][x] += match (y == 0, x == 0) {
-                (true, true) => 0,
-                (true, false) => grid[y][x-1],
-                (false, true) => grid[y-1][x],
-                (false, false) => grid[y-1][x] + grid[y][x-1] - grid[y-1][x-1],
+struct PowerGrid {
+    width: usize,
+    height: usize,
+    sums: Vec<i32>,
+    max_cell: i32,
+    min_cell: i32,
+}
+
+impl PowerGrid {
+    fn new(width: usize, height: usize, serial: i32) -> PowerGrid {
+        PowerGrid::from_fn(width, height,
+                           |x, y| cell_power(x as i32, y as i32, serial))
+    }
+
+    fn from_fn<F>(width: usize, height: usize, power: F) -> PowerGrid
+            where F: Fn(usize, usize) -> i32 {
+        // Summed-area table with an extra row and column of zeros, so
+        // cell (x, y) holds the sum of every cell up to and including it
+        let stride = width + 1;
+        let mut sums = vec![0; stride*(height+1)];
+        let mut max_cell = i32::MIN;
+        let mut min_cell = i32::MAX;
+        for y in 1..height+1 {
+            for x in 1..width+1 {
+                let power = power(x, y);
+                max_cell = cmp::max(max_cell, power);
+                min_cell = cmp::min(min_cell, power);
+                sums[y*stride + x] = power
+                                     + sums[(y-1)*stride + x]
+                                     + sums[y*stride + x-1]
+                                     - sums[(y-1)*stride + x-1];
             }
         }
+        PowerGrid { width, height, sums, max_cell, min_cell }
     }
-}
 
-fn sum_of_area(grid: & Vec<Vec<i32>>, x: usize, y: usize, size: usize) -> i32 {
-    let size = size-1;
-    match (x == 0, y == 0) {
-        (true, true) => grid[y+size][x+size],
-        (true, false) => grid[y+size][x+size] - grid[y-1][x+size],
-        (false, true) => grid[y+size][x+size] - grid[y+size][x-1],
-        (false, false) => grid[y+size][x+size] - grid[y+size][x-1]
-                          - grid[y-1][x+size] + grid[y-1][x-1],
+    fn sum_of_area(&self, x: usize, y: usize, width: usize, height: usize) -> i32 {
+        let stride = self.width + 1;
+        let (left, top) = (x - 1, y - 1);
+        let (right, bottom) = (left + width, top + height);
+        self.sums[bottom*stride + right] - self.sums[top*stride + right]
+            - self.sums[bottom*stride + left] + self.sums[top*stride + left]
     }
-}
 
-fn find_max_at_size(grid: &Vec<Vec<i32>>, size: usize) -> (i32, i32, i32) {
-    let mut max = (sum_of_area(grid, 0, 0, size), 0, 0);
-    for y in 0..300-size+1 {
-        for x in 0..300-size+1 {
-            max = cmp::max(max, (sum_of_area(grid, x, y, size), x, y));
+    fn find_max_among<I>(&self, sizes: I) -> Option<Rect>
+            where I: Iterator<Item = (usize, usize)> {
+        let mut max: Option<Rect> = None;
+        for (width, height) in sizes {
+            if width == 0 || height == 0
+               || width > self.width || height > self.height {
+                continue;
+            }
+            for y in 1..self.height-height+2 {
+                for x in 1..self.width-width+2 {
+                    let power = self.sum_of_area(x, y, width, height);
+                    if max.is_none_or(|max| power > max.power) {
+                        max = Some(Rect { power, x, y, width, height });
+                    }
+                }
+            }
         }
+        max
+    }
+
+    /// Most powerful rectangle of any of the given widths and heights,
+    /// None if none of them fit.
+    fn find_max_rect(&self, widths: RangeInclusive<usize>,
+                     heights: RangeInclusive<usize>) -> Option<Rect> {
+        let sizes = heights.flat_map(|h| widths.clone().map(move |w| (w, h)));
+        self.find_max_among(sizes)
+    }
+
+    fn find_max_at_size(&self, size: usize) -> (i32, i32, i32) {
+        let max = self.find_max_rect(size..=size, size..=size).unwrap();
+        (max.power, max.x as i32, max.y as i32)
     }
-    (max.0, max.1 as i32 + 1, max.2 as i32 + 1)
-}
 
-fn find_max(grid: &Vec<Vec<i32>>) -> (i32, i32, i32) {
-    let mut max = (grid[0][0], 0, 0, 1);
-    for size in 1..301 {
-        let vxy = find_max_at_size(grid, size);
-        max = cmp::max(max, (vxy.0, vxy.1, vxy.2, size));
+    fn find_max(&self) -> (i32, i32, i32) {
+        let max_size = cmp::min(self.width, self.height);
+        let max = self.top_squares(1..=max_size, 1, 1)[0];
+        (max.x as i32, max.y as i32, max.width as i32)
+    }
+
+    fn top_at_size(&self, size: usize, k: usize) -> Vec<Rect> {
+        let mut top = BinaryHeap::new();
+        for y in 1..self.height-size+2 {
+            for x in 1..self.width-size+2 {
+                let power = self.sum_of_area(x, y, size, size);
+                push_bounded(&mut top, &Rect { power, x, y, width: size,
+                                               height: size }, k);
+            }
+        }
+        top.into_sorted_vec().into_iter().map(|Reverse(rank)| unrank(rank)).collect()
+    }
+
+    fn square_bound(&self, size: usize, bounds: &[i32]) -> i32 {
+        // A square is at most the next smaller square plus a strip of
+        // 2*size-1 cells, and at most the sum of its four quadrants. Of
+        // those the rectangular ones are at most a square missing a strip.
+        let mut bound = (size*size) as i32 * self.max_cell;
+        if size > 1 {
+            let grown = bounds[size-1] + (2*size-1) as i32 * self.max_cell;
+            let (a, b) = (size.div_ceil(2), size/2);
+            let quadrants = if a == b {
+                4*bounds[a]
+            } else {
+                bounds[a] + bounds[b] + 2*(bounds[a] - a as i32 * self.min_cell)
+            };
+            bound = cmp::min(bound, cmp::min(grown, quadrants));
+        }
+        bound
+    }
+
+    /// The k most powerful squares with sizes in range, best first. Sizes
+    /// that can't beat the squares found so far are skipped, and up to
+    /// `threads` sizes are searched at once.
+    fn top_squares(&self, sizes: RangeInclusive<usize>, k: usize,
+                   threads: usize) -> Vec<Rect> {
+        let first = *sizes.start();
+        let last = cmp::min(*sizes.end(), cmp::min(self.width, self.height));
+        if k == 0 {
+            return Vec::new();
+        }
+
+        // Upper bound on the best square of each size, exact once searched
+        let mut bounds = vec![0; last + 1];
+        let mut top: BinaryHeap<Reverse<Rank>> = BinaryHeap::new();
+        let mut size = 1;
+        while size <= last {
+            let mut batch = Vec::new();
+            while batch.len() < cmp::max(threads, 1) && size <= last {
+                bounds[size] = self.square_bound(size, &bounds);
+                let hopeless = match top.peek() {
+                    Some(&Reverse(worst)) if top.len() == k => bounds[size] <= worst.0,
+                    _ => false,
+                };
+                if size >= first && !hopeless {
+                    batch.push(size);
+                }
+                size += 1;
+            }
+
+            let found: Vec<Vec<Rect>> = if batch.len() > 1 {
+                thread::scope(|scope| {
+                    let handles: Vec<_> = batch.iter()
+                        .map(|&size| scope.spawn(move || self.top_at_size(size, k)))
+                        .collect();
+                    handles.into_iter().map(|h| h.join().unwrap()).collect()
+                })
+            } else {
+                batch.iter().map(|&size| self.top_at_size(size, k)).collect()
+            };
+
+            for (&size, squares) in batch.iter().zip(found) {
+                bounds[size] = squares[0].power;
+                for square in &squares {
+                    push_bounded(&mut top, square, k);
+                }
+            }
+        }
+        top.into_sorted_vec().into_iter().map(|Reverse(rank)| unrank(rank)).collect()
     }
-    (max.1, max.2, max.3 as i32)
 }
 
 fn main() {
-    let mut grid = create_grid(4151);
-    integrate(&mut grid);
-    println!("post integrate");
-    let max = find_max_at_size(&grid, 3);
+    let grid = PowerGrid::new(300, 300, 4151);
+    let max = grid.find_max_at_size(3);
     println!("3x3 coordinates: {},{}", max.1, max.2);
-    let max = find_max(&grid);
+    let max = grid.find_max();
     println!("Unrestricted size: size {} at {},{}", max.2, max.0, max.1);
+
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    for square in grid.top_squares(1..=300, 5, threads) {
+        println!("Power {}: size {} at {},{}", square.power, square.width,
+                 square.x, square.y);
+    }
 }
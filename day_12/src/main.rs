@@ -1,5 +1,7 @@
 use std::io::prelude::*;
 use std::fs::File;
+use std::env;
+use std::collections::HashMap;
 use std::collections::VecDeque;
 
 #[cfg(test)]
@@ -26,7 +28,7 @@ mod tests {
     #[test]
     fn test_both_halves() {
         let input = String::from(TEST_INPUT);
-        let (mut pots, rules) = parse_input(input);
+        let (mut pots, rules) = parse_input(input).unwrap();
 
         for _ in 0..20 {
             pots.evolve(&rules);
@@ -34,107 +36,280 @@ mod tests {
 
         assert_eq!(pots.sum_plants(), 325);
     }
+
+    #[test]
+    fn test_cycle() {
+        let input = String::from(TEST_INPUT);
+        let (initial, rules) = parse_input(input).unwrap();
+        let (sum, cycle) = initial.sum_after(&rules, 20);
+        assert_eq!(sum, 325);
+        assert!(cycle.is_none());
+
+        let (_, cycle) = initial.sum_after(&rules, 50_000_000_000);
+        let cycle = cycle.unwrap();
+        assert_eq!((cycle.start, cycle.period, cycle.shift), (86, 1, 1));
+
+        let mut pots = initial.clone();
+        for generation in 0..300 {
+            assert_eq!(initial.sum_after(&rules, generation).0, pots.sum_plants());
+            if generation >= cycle.start {
+                assert_eq!(cycle.sum_plants(generation), pots.sum_plants());
+            }
+            pots.evolve(&rules);
+        }
+    }
+
+    #[test]
+    fn test_general_rules() {
+        let rule_90 = Rules::wolfram(90, 1, 2).unwrap();
+        let mut cells = Automaton::new(vec![1], 0);
+        for &expected in &["#.#", "#...#", "#.#.#.#"] {
+            cells.evolve(&rule_90);
+            assert_eq!(cells.render(".#"), expected);
+        }
+        assert_eq!(cells.offset, -3);
+
+        let rule_30 = Rules::wolfram(30, 1, 2).unwrap();
+        let mut cells = Automaton::new(vec![1], 0);
+        cells.evolve(&rule_30);
+        assert_eq!((cells.render(".#").as_str(), cells.offset), ("###", -1));
+        cells.evolve(&rule_30);
+        assert_eq!((cells.render(".#").as_str(), cells.offset), ("##..#", -2));
+        assert!(Rules::wolfram(1, 1, 2).is_err());
+        assert!(Rules::wolfram(7, 1, 3).is_err());
+        assert!(Rules::wolfram(346, 1, 2).is_err());
+        assert!(Rules::wolfram(254, 1, 2).is_ok());
+
+        let rules = Rules::parse("..a => a\n.a. => b\na.. => a".lines(), ".ab").unwrap();
+        assert_eq!((rules.radius, rules.states), (1, 3));
+        let mut cells = Automaton::new(parse_states("..a.", ".ab").unwrap(), 0);
+        assert_eq!(cells.offset, 2);
+        cells.evolve(&rules);
+        assert_eq!((cells.render(".ab").as_str(), cells.offset), ("aba", 1));
+
+        assert!(Rules::parse("..## => #".lines(), ".#").is_err());
+        assert!(Rules::parse("..#.. => x".lines(), ".#").is_err());
+        assert!(Rules::parse("..#.. => #\n..... => #".lines(), ".#").is_err());
+    }
 }
 
+/// The pots repeat every `period` generations from `start` on, shifted
+/// `shift` pots to the right each time. Holds the sum and number of plants
+/// for each generation of the first period.
 #[derive(Debug, Clone)]
-struct Pots {
-    living: VecDeque<bool>,
-    offset: isize,
+struct Cycle {
+    start: u64,
+    period: u64,
+    shift: isize,
+    sums: Vec<i64>,
+    plants: Vec<i64>,
 }
 
-impl Pots {
+impl Cycle {
+    fn sum_plants(&self, generation: u64) -> i64 {
+        assert!(generation >= self.start, "Generation precedes the cycle");
+        let periods = ((generation - self.start) / self.period) as i64;
+        let phase = ((generation - self.start) % self.period) as usize;
+        self.sums[phase] + periods * self.shift as i64 * self.plants[phase]
+    }
+}
 
-    fn evolve(&mut self, rules: &[bool; 32]) {
+/// Rule table for a one dimensional automaton with `states` cell states,
+/// each cell's next state depending on the `radius` cells either side of
+/// it. Neighbourhoods are read as base `states` numbers, leftmost cell
+/// most significant. State 0 is the background and must stay that way.
+#[derive(Debug, Clone)]
+struct Rules {
+    radius: usize,
+    states: usize,
+    table: Vec<u8>,
+}
 
-        let mut leftmost = 0;        
-        for i in 0..self.living.len()-2 {
-            leftmost = leftmost*2 + self.living[i+2] as usize;
-            self.living[i] = rules[leftmost];
-            leftmost &= 0b1111;
+impl Rules {
+    fn new(radius: usize, states: usize) -> Rules {
+        let neighbourhoods = states.pow(2*radius as u32 + 1);
+        Rules { radius, states, table: vec![0; neighbourhoods] }
+    }
+
+    /// Digit `i` of `number` in base `states` is the result for
+    /// neighbourhood `i`, e.g. rule 90 with radius 1 and two states. Fails
+    /// for rules that would fill the background, those with a non-zero
+    /// lowest digit, and for numbers with more digits than neighbourhoods.
+    fn wolfram(number: u64, radius: usize, states: usize) -> Result<Rules, String> {
+        if !number.is_multiple_of(states as u64) {
+            return Err(format!("Rule {} changes the background", number));
+        }
+        let mut rules = Rules::new(radius, states);
+        let mut number = number;
+        for result in rules.table.iter_mut() {
+            *result = (number % states as u64) as u8;
+            number /= states as u64;
         }
+        if number != 0 {
+            return Err(format!("Rule number too large for {} neighbourhoods",
+                               rules.table.len()));
+        }
+        Ok(rules)
+    }
 
-        if !self.living[0] && !self.living[1] && !self.living[2] {
-            while !self.living[2] {
-                self.living.pop_front();
-                self.offset += 1;
+    /// Rules in the form `..#.# => #`, where `symbols` lists the character
+    /// for each state. Neighbourhoods without a rule become state 0. Fails
+    /// for rules that would fill the background.
+    fn parse<'a, I>(lines: I, symbols: &str) -> Result<Rules, String>
+            where I: Iterator<Item = &'a str> {
+        let mut rules: Option<Rules> = None;
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
             }
-        } else {
-            while self.living[0] || self.living[1] {
-                self.living.push_front(false);
-                self.offset -= 1;
+
+            let mut halves = line.split(" => ");
+            let (cause, result) = match (halves.next(), halves.next()) {
+                (Some(cause), Some(result)) => (cause, result),
+                _ => return Err(format!("Malformed rule: {}", line)),
+            };
+            let cause = parse_states(cause, symbols)?;
+            let result = parse_states(result, symbols)?;
+            if cause.len() % 2 == 0 || result.len() != 1 {
+                return Err(format!("Malformed rule: {}", line));
             }
-        }
 
-        for i in 0..4 {
-            if self.living[self.living.len()-1-i] {
-                for _ in 0..4-i {
-                    self.living.push_back(false);
-                }
-                break;
+            let rules = rules.get_or_insert_with(
+                || Rules::new(cause.len() / 2, symbols.chars().count()));
+            if cause.len() != 2*rules.radius + 1 {
+                return Err(format!("Rule of a different radius: {}", line));
             }
+            let index = cause.iter()
+                             .fold(0, |index, &state| index*rules.states + state as usize);
+            rules.table[index] = result[0];
         }
-
-        while !self.living[self.living.len()-5] {
-            self.living.pop_back();
+        let rules = rules.ok_or_else(|| String::from("No rules given"))?;
+        if rules.table[0] != 0 {
+            return Err(String::from("Rules change the background"));
         }
+        Ok(rules)
     }
+}
 
-    fn sum_plants(&self) -> i64 {
-        let mut sum: i64 = 0;
-        for i in 0..self.living.len() {
-            if self.living[i] {
-                sum += (i as isize + self.offset) as i64;
-            }
-        }
-        sum
-    }
+fn parse_states(cells: &str, symbols: &str) -> Result<Vec<u8>, String> {
+    cells.chars()
+         .map(|c| symbols.chars().position(|s| s == c)
+                         .map(|state| state as u8)
+                         .ok_or_else(|| format!("Unknown cell state: {}", c)))
+         .collect()
 }
 
-fn parse_input(input: String) -> (Pots, [bool; 32]) {
-    let mut lines = input.lines();
+/// Cells of an automaton, trimmed to those between the first and last
+/// cell not in the background state. `offset` is the first one's position.
+#[derive(Debug, Clone)]
+struct Automaton {
+    cells: VecDeque<u8>,
+    offset: isize,
+}
 
-    let initial_state: VecDeque<bool> = lines.next().unwrap()
-                                             .split(' ').nth(2)
-                                             .unwrap().trim()
-                                             .bytes().map(|b| b == '#' as u8)
-                                             .collect();
+/// Pots are cells that either have a plant or not.
+type Pots = Automaton;
 
-    let mut pots = Pots{ living: initial_state, offset: -2 };
+impl Automaton {
+    fn new(cells: Vec<u8>, offset: isize) -> Automaton {
+        let mut automaton = Automaton { cells: cells.into_iter().collect(),
+                                        offset };
+        automaton.trim();
+        automaton
+    }
 
-    for _ in 0..4 {
-        pots.living.push_back(false);
+    fn trim(&mut self) {
+        while self.cells.front() == Some(&0) {
+            self.cells.pop_front();
+            self.offset += 1;
+        }
+        while self.cells.back() == Some(&0) {
+            self.cells.pop_back();
+        }
     }
 
-    pots.living.push_front(false);
-    pots.living.push_front(false);
+    fn evolve(&mut self, rules: &Rules) {
+        assert!(rules.table[0] == 0, "Background must stay in state 0");
 
+        // Cells up to `radius` outside the current ones can change. Each
+        // neighbourhood is rolled along from the last one, so the cell
+        // read at step `i` completes the one centred `radius` cells back.
+        let neighbourhoods = rules.table.len();
+        let mut index = 0;
+        let mut next = VecDeque::with_capacity(self.cells.len() + 2*rules.radius);
+        for i in 0..self.cells.len() + 2*rules.radius {
+            let state = self.cells.get(i).cloned().unwrap_or(0);
+            index = (index*rules.states + state as usize) % neighbourhoods;
+            next.push_back(rules.table[index]);
+        }
+        self.offset -= rules.radius as isize;
+        self.cells = next;
+        self.trim();
+    }
 
-    lines.next();
+    /// Number of cells not in the background state.
+    fn count_plants(&self) -> i64 {
+        self.cells.iter().filter(|&&state| state != 0).count() as i64
+    }
 
-    let mut rules = [false; 32];
-    for line in lines {
-        let line = line.trim();
-        if line.is_empty() {
-            continue;
+    /// Sum of positions of cells not in the background state.
+    fn sum_plants(&self) -> i64 {
+        let mut sum: i64 = 0;
+        for (i, &state) in self.cells.iter().enumerate() {
+            if state != 0 {
+                sum += (i as isize + self.offset) as i64;
+            }
         }
+        sum
+    }
 
-        let mut halves = line.split(" => ");
-        let cause = halves.next().unwrap();
-        let result = halves.next().unwrap();
-        if result == "." {
-            continue;
-        }
+    /// Sum of plants after `generations`, extrapolated once the cells repeat
+    /// themselves, possibly shifted. Returns the cycle if one was found.
+    fn sum_after(&self, rules: &Rules, generations: u64) -> (i64, Option<Cycle>) {
+        // Cells are always trimmed, so equal patterns have equal `cells`
+        let mut automaton = self.clone();
+        let mut seen = HashMap::new();
+        let mut sums = Vec::new();
+        let mut plants = Vec::new();
+        for generation in 0.. {
+            if generation == generations {
+                return (automaton.sum_plants(), None);
+            }
 
-        let mut byte_val = 0;
-        for byte in cause.bytes() {
-            byte_val *= 2;
-            if byte == '#' as u8 {
-                byte_val |= 0b1;
+            if let Some(&(start, offset)) = seen.get(&automaton.cells) {
+                let first = start as usize;
+                let cycle = Cycle { start, period: generation - start,
+                                    shift: automaton.offset - offset,
+                                    sums: sums.split_off(first),
+                                    plants: plants.split_off(first) };
+                return (cycle.sum_plants(generations), Some(cycle));
             }
+
+            seen.insert(automaton.cells.clone(), (generation, automaton.offset));
+            sums.push(automaton.sum_plants());
+            plants.push(automaton.count_plants());
+            automaton.evolve(rules);
         }
-        rules[byte_val] = true;
+        unreachable!()
+    }
+
+    fn render(&self, symbols: &str) -> String {
+        let symbols: Vec<char> = symbols.chars().collect();
+        self.cells.iter().map(|&state| symbols[state as usize]).collect()
     }
-    (pots, rules)
+}
+
+fn parse_input(input: String) -> Result<(Pots, Rules), String> {
+    let mut lines = input.lines();
+
+    let initial_state = lines.next()
+                             .and_then(|line| line.split(' ').nth(2))
+                             .ok_or_else(|| String::from("Missing initial state"))?;
+    let pots = Pots::new(parse_states(initial_state.trim(), ".#")?, 0);
+
+    let rules = Rules::parse(lines, ".#")?;
+    Ok((pots, rules))
 }
 
 fn main() {
@@ -142,17 +317,34 @@ fn main() {
     let mut f = File::open("input").expect("Failed to open input.");
     f.read_to_string(&mut input).expect("Failed to read input.");
 
-    let (mut pots, rules) = parse_input(input);
+    let (pots, rules) = parse_input(input).expect("Failed to parse input.");
 
-    for _ in 0..20 {
-        pots.evolve(&rules);
-    }
-
-    println!("Value after 20 steps: {}", pots.sum_plants());
+    println!("Value after 20 steps: {}", pots.sum_after(&rules, 20).0);
 
-    for _ in 20u64..50_000_000_000u64 {
-        pots.evolve(&rules);
+    let (sum, cycle) = pots.sum_after(&rules, 50_000_000_000);
+    println!("Value after 50_000_000_000 steps: {}", sum);
+    if let Some(cycle) = cycle {
+        println!("Repeats every {} steps from step {}, shifting by {}",
+                 cycle.period, cycle.start, cycle.shift);
     }
 
-    println!("Value after 50_000_000_000 steps: {}", pots.sum_plants());
+    // Optionally draw an elementary automaton grown from a single cell
+    let mut args = env::args().skip(1);
+    if let Some(arg) = args.next() {
+        let number = arg.parse().expect("Invalid rule number.");
+        let generations = args.next().map_or(16, |n| n.parse().expect("Invalid generations."));
+        let rules = match Rules::wolfram(number, 1, 2) {
+            Ok(rules) => rules,
+            Err(error) => {
+                eprintln!("{}", error);
+                return;
+            },
+        };
+        let mut cells = Automaton::new(vec![1], 0);
+        for _ in 0..generations {
+            println!("{}{}", " ".repeat((cells.offset + generations as isize) as usize),
+                     cells.render(" #"));
+            cells.evolve(&rules);
+        }
+    }
 }
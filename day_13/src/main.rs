@@ -1,8 +1,11 @@
 use std::io::prelude::*;
 use std::fs::File;
+use std::env;
 use std::collections::BinaryHeap;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::cmp::Ordering;
+use std::fmt;
 
 #[cfg(test)]
 mod tests {
@@ -16,12 +19,187 @@ mod tests {
                               - \\------/   \n\
                               ";
 
+    const CRASH_INPUT: &str = "/>-<\\  \n\
+                               |   |  \n\
+                               | /<+-\\\n\
+                               | | | v\n\
+                               \\>+</ |\n\
+                               \x20 |   ^\n\
+                               \x20 \\<->/\n";
+
+    // Carts 0 and 1 crash on the intersection, then cart 2 moves onto it
+    const SHARED_INPUT: &str = "  v\n >+<\n  |\n  |\n";
+
     #[test]
     fn test_both_halves() {
         let input = String::from(TEST_INPUT);
-        let (network, mut carts, mut cart_pos) = parse_input(input);
-        let first_collision = simulate(&network, &mut carts, &mut cart_pos);
-        assert_eq!(first_collision, (7, 3));
+        let (network, carts) = parse_input(input);
+        let run = Simulation::new(network, carts).run();
+        assert_eq!(run.collisions[0].position, (7, 3));
+        assert_eq!(run.collisions[0].tick, 13);
+        assert!(run.survivor.is_none());
+    }
+
+    #[test]
+    fn test_replay() {
+        let (network, carts) = parse_input(String::from(CRASH_INPUT));
+        let run = Simulation::new(network.clone(), carts.clone()).run();
+        assert_eq!(run.survivor.unwrap().coordinate, (6, 4));
+        assert_eq!(run.collisions.iter()
+                      .map(|collision| (collision.tick, collision.position))
+                      .collect::<Vec<_>>(),
+                   vec![(0, (2, 0)), (0, (2, 4)), (0, (6, 4)), (2, (2, 4))]);
+        assert_eq!(run.trajectories.len(), 9);
+        assert_eq!(run.trajectories[6].positions, vec![(6, 5), (6, 4)]);
+        assert_eq!(run.trajectories[8].positions,
+                   vec![(5, 6), (6, 6), (6, 5), (6, 4)]);
+
+        // Stepping by hand sees the same collisions, tick by tick
+        let mut simulation = Simulation::new(network, carts);
+        assert_eq!(simulation.step().len(), 3);
+        assert_eq!((simulation.tick, simulation.remaining()), (1, 3));
+        assert!(simulation.step().is_empty());
+        assert_eq!(simulation.step()[0], run.collisions[3]);
+        assert_eq!(simulation.remaining(), 1);
+        assert_eq!(simulation.collisions, run.collisions);
+    }
+
+    #[test]
+    fn test_shared_square() {
+        let (network, carts) = parse_input(String::from(SHARED_INPUT));
+        let mut simulation = Simulation::new(network.clone(), carts.clone());
+        assert_eq!(simulation.step(),
+                   &[Collision { tick: 0, position: (2, 1), carts: (1, 0) }]);
+        let live = |simulation: &Simulation| simulation.live_carts()
+            .map(|cart| (cart.id, cart.coordinate, cart.direction))
+            .collect::<Vec<_>>();
+        assert_eq!(live(&simulation), vec![(2, (2, 1), Direction::Down)]);
+        simulation.step();
+        assert_eq!(live(&simulation), vec![(2, (2, 2), Direction::Down)]);
+
+        let run = Simulation::new(network, carts).run();
+        assert_eq!(run.survivor.map(|cart| (cart.id, cart.coordinate)), Some((2, (2, 1))));
+    }
+
+    #[test]
+    fn test_turn_policies() {
+        let policy = TurnPolicy::parse("LSR").unwrap();
+        assert_eq!(policy, TurnPolicy::standard());
+        assert_eq!((0..4).map(|i| policy.turn_at(0, i)).collect::<Vec<_>>(),
+                   vec![Direction::Left, Direction::Up, Direction::Right, Direction::Left]);
+        assert!(TurnPolicy::parse("LX").is_err());
+        assert!(TurnPolicy::parse("").is_err());
+
+        let random = TurnPolicy::parse("random:7").unwrap();
+        assert_eq!(random, TurnPolicy::Random(7));
+        let turns = (0..30).map(|i| random.turn_at(1, i)).collect::<Vec<_>>();
+        assert_eq!(turns, (0..30).map(|i| random.turn_at(1, i)).collect::<Vec<_>>());
+        assert!([Direction::Left, Direction::Up, Direction::Right].iter()
+                    .all(|direction| turns.contains(direction)));
+
+        // Going straight on, the example's carts never meet
+        let (network, carts) = parse_input(String::from(TEST_INPUT));
+        let mut simulation = Simulation::new(network.clone(), carts.clone());
+        simulation.policy = TurnPolicy::parse("S").unwrap();
+        for _ in 0..100 {
+            assert!(simulation.step().is_empty());
+        }
+        simulation.max_ticks = 1000;
+        let run = simulation.clone().run();
+        assert!(run.collisions.is_empty() && run.survivor.is_none());
+        assert_eq!(run.remaining, 2);
+
+        // Unless one of them turns, with carts otherwise going straight on
+        let mut simulation = Simulation::new(network, carts);
+        simulation.policy = TurnPolicy::parse("S").unwrap();
+        simulation.cart_policies.insert(1, TurnPolicy::standard());
+        let run = simulation.run();
+        assert_eq!(run.collisions.len(), 1);
+        assert_eq!(run.collisions[0].carts, (1, 0));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let (network, carts) = parse_input(String::from(TEST_INPUT));
+        assert_eq!(carts.iter().map(|cart| cart.id).collect::<Vec<_>>(), vec![0, 1]);
+        assert_ne!(carts[0], Cart { id: 1, ..carts[0] });
+        assert_ne!(carts[0], Cart { intersections: 1, ..carts[0] });
+
+        let mut simulation = Simulation::new(network.clone(), carts);
+        let snapshot = simulation.snapshot();
+        assert_eq!(snapshot.map, TEST_INPUT);
+        assert_eq!(snapshot.carts, vec![(0, 0), (1, 0)]);
+
+        // Both carts are on curves or intersections after 4 ticks
+        for _ in 0..4 {
+            simulation.step();
+        }
+        let snapshot = simulation.snapshot();
+        assert_eq!(snapshot.map.lines().nth(3), Some("| | |  | |  |"));
+        assert_eq!(snapshot.carts, vec![(0, 1), (1, 1)]);
+
+        assert_eq!(Snapshot::parse(&snapshot.to_string()), Ok(snapshot.clone()));
+        let mut restored = Simulation::restore(network.clone(), &snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        for _ in 0..6 {
+            simulation.step();
+            restored.step();
+            assert_eq!(restored.snapshot(), simulation.snapshot());
+        }
+        assert_eq!(restored.run().collisions, simulation.run().collisions[..1]);
+
+        let broken = Snapshot { carts: Vec::new(), ..snapshot };
+        assert!(Simulation::restore(network, &broken).is_err());
+
+        // Only the cart that moved onto the crash site is saved
+        let (network, carts) = parse_input(String::from(SHARED_INPUT));
+        let mut simulation = Simulation::new(network.clone(), carts);
+        simulation.step();
+        let snapshot = simulation.snapshot();
+        assert_eq!(snapshot.map, "  |\n -v-\n  |\n  |\n");
+        assert_eq!(snapshot.carts, vec![(2, 1)]);
+        let mut restored = Simulation::restore(network, &snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        simulation.step();
+        restored.step();
+        assert_eq!(restored.snapshot(), simulation.snapshot());
+    }
+
+    #[test]
+    fn test_validate() {
+        let validate_input = |input: &str| {
+            let (network, carts) = parse_input(String::from(input));
+            validate(&network, &carts)
+        };
+        // The example's last line starts with a stray piece of track
+        assert_eq!(validate_input(TEST_INPUT),
+                   Err(vec![TrackError::Dangling((0, 5), Direction::Left),
+                            TrackError::Dangling((0, 5), Direction::Right)]));
+        assert_eq!(validate_input(CRASH_INPUT), Ok(()));
+
+        assert_eq!(validate_input("/-\\\n| |\n\\-"),
+                   Err(vec![TrackError::Dangling((2, 1), Direction::Down),
+                            TrackError::Dangling((1, 2), Direction::Right)]));
+        assert_eq!(validate_input("/-\\\n| |\n\\->"),
+                   Err(vec![TrackError::CartOnCurve((2, 2))]));
+        assert_eq!(validate_input("/---\\\n\
+                                   |   |\n\
+                                   | /-v-\\\n\
+                                   | | | |\n\
+                                   \\-+-/ |\n\
+                                   \x20 |   |\n\
+                                   \x20 \\---/"),
+                   Err(vec![TrackError::CartOnIntersection((4, 2))]));
+        assert_eq!(validate_input("/"),
+                   Err(vec![TrackError::UnresolvedCurve((0, 0))]));
+
+        // Curves packed next to curves are settled through each other
+        assert_eq!(validate_input("  /---\\\n\
+                                   \x20 \\---/\n\
+                                   /\\/---\\\n\
+                                   |||   |\n\
+                                   \\/\\---/"),
+                   Ok(()));
     }
 }
 
@@ -33,20 +211,22 @@ enum Direction {
     Right,
 }
 
-#[derive(Debug, Eq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 struct Cart {
     coordinate: (usize, usize),
     direction: Direction,
     tick: u32,
-    next_turn: Direction,
+    intersections: u32,
     id: u32,
 }
 
 impl Ord for Cart {
     fn cmp(&self, other: &Cart) -> Ordering {
         // Reverse order; to make min heap
+        let reading_order = |cart: &Cart| (cart.coordinate.1, cart.coordinate.0);
         other.tick.cmp(&self.tick)
-             .then(other.coordinate.cmp(&self.coordinate))
+             .then(reading_order(other).cmp(&reading_order(self)))
+             .then(other.id.cmp(&self.id))
     }
 }
 
@@ -56,14 +236,6 @@ impl PartialOrd for Cart {
     }
 }
 
-impl PartialEq for Cart {
-    fn eq(&self, other: &Cart) -> bool {
-        self.tick == other.tick &&
-            self.coordinate == other.coordinate &&
-            self.direction == other.direction
-    }
-}
-
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 enum TurnDir {
     TwoEight, // '/'
@@ -79,19 +251,41 @@ enum Rail {
     Turn(TurnDir),
 }
 
-fn parse_input(input: String)
-        -> (Vec<Vec<Rail>>, BinaryHeap<Cart>, HashSet<(usize, usize)>) {
+fn rail_symbol(rail: Rail) -> char {
+    match rail {
+        Rail::Empty => ' ',
+        Rail::Horizontal => '-',
+        Rail::Vertical => '|',
+        Rail::Intersection => '+',
+        Rail::Turn(TurnDir::TwoEight) => '/',
+        Rail::Turn(TurnDir::FourTen) => '\\',
+    }
+}
+
+fn cart_symbol(direction: Direction) -> char {
+    match direction {
+        Direction::Up => '^',
+        Direction::Left => '<',
+        Direction::Down => 'v',
+        Direction::Right => '>',
+    }
+}
 
+fn cart_direction(symbol: char) -> Option<Direction> {
+    [Direction::Up, Direction::Left, Direction::Down, Direction::Right].iter()
+        .find(|&&direction| cart_symbol(direction) == symbol)
+        .cloned()
+}
+
+/// Carts get ids in reading order, starting from 0.
+fn parse_input(input: String) -> (Vec<Vec<Rail>>, Vec<Cart>) {
     let mut carts: Vec<Cart> = Vec::new();
-    let mut network = Vec::new(); 
+    let mut network = Vec::new();
 
-    let mut y = 0; 
-    for line in input.lines() {
+    for (y, line) in input.lines().enumerate() {
         network.push(Vec::new());
 
-        let mut x = 0;
-        let mut num_carts = 0;
-        for byte in line.bytes() {
+        for (x, byte) in line.bytes().enumerate() {
             let next_bit = match byte as char {
                 ' ' => Rail::Empty,
                 '-' => Rail::Horizontal,
@@ -99,48 +293,219 @@ fn parse_input(input: String)
                 '+' => Rail::Intersection,
                 '/' => Rail::Turn(TurnDir::TwoEight),
                 '\\' => Rail::Turn(TurnDir::FourTen),
-
-                '>' => {carts.push(Cart { coordinate: (x,y),
-                                          direction: Direction::Right,
-                                          next_turn: Direction::Left,
-                                          tick: 0, id: num_carts});
-                        num_carts += 1;
-                        Rail::Horizontal}
-                '<' => {carts.push(Cart { coordinate: (x,y),
-                                          direction: Direction::Left,
-                                          next_turn: Direction::Left,
-                                          tick: 0, id: num_carts});
-                        num_carts += 1;
-                        Rail::Horizontal}
-                '^' => {carts.push(Cart { coordinate: (x,y),
-                                          direction: Direction::Up,
-                                          next_turn: Direction::Left,
-                                          tick: 0, id: num_carts});
-                        num_carts += 1;
-                        Rail::Vertical}
-                'v' => {carts.push(Cart { coordinate: (x,y),
-                                          direction: Direction::Down,
-                                          next_turn: Direction::Left,
-                                          tick: 0, id: num_carts});
-                        num_carts += 1;
-                        Rail::Vertical}
-                _ => panic!("Unknown symbol: {}", byte as char),
+                symbol => match cart_direction(symbol) {
+                    Some(direction) => {
+                        carts.push(Cart { coordinate: (x,y), direction,
+                                          intersections: 0,
+                                          tick: 0, id: carts.len() as u32 });
+                        match direction {
+                            Direction::Left | Direction::Right => Rail::Horizontal,
+                            Direction::Up | Direction::Down => Rail::Vertical,
+                        }
+                    },
+                    None => panic!("Unknown symbol: {}", symbol),
+                },
             };
             network.last_mut().unwrap()
                    .push(next_bit);
-            x += 1;
         }
-        y += 1;
     }
 
-    let mut cart_heap = BinaryHeap::new();
-    let mut cart_pos = HashSet::new();
+    (network, carts)
+}
+
+/// Draws carts over the network, one line per row.
+fn render<'a, I>(network: &[Vec<Rail>], carts: I) -> String
+        where I: Iterator<Item = &'a Cart> {
+    let mut map: Vec<Vec<char>> = network.iter()
+        .map(|row| row.iter().map(|&rail| rail_symbol(rail)).collect())
+        .collect();
+    for cart in carts {
+        map[cart.coordinate.1][cart.coordinate.0] = cart_symbol(cart.direction);
+    }
+
+    let mut rendered = String::new();
+    for row in map {
+        rendered.extend(row);
+        rendered.push('\n');
+    }
+    rendered
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum TrackError {
+    Dangling((usize, usize), Direction),
+    UnresolvedCurve((usize, usize)),
+    CartOnCurve((usize, usize)),
+    CartOnIntersection((usize, usize)),
+}
+
+impl fmt::Display for TrackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrackError::Dangling((x, y), direction) =>
+                write!(f, "Track at {},{} leads {:?} to nothing", x, y, direction),
+            TrackError::UnresolvedCurve((x, y)) =>
+                write!(f, "Curve at {},{} can't be joined to its neighbours", x, y),
+            TrackError::CartOnCurve((x, y)) =>
+                write!(f, "Cart at {},{} starts on a curve", x, y),
+            TrackError::CartOnIntersection((x, y)) =>
+                write!(f, "Cart at {},{} starts on an intersection", x, y),
+        }
+    }
+}
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Left,
+                                    Direction::Down, Direction::Right];
+
+fn opposite(direction: Direction) -> Direction {
+    turn_left(turn_left(direction))
+}
+
+fn neighbour(position: (usize, usize), direction: Direction) -> Option<(usize, usize)> {
+    let (x, y) = position;
+    match direction {
+        Direction::Up => y.checked_sub(1).map(|y| (x, y)),
+        Direction::Left => x.checked_sub(1).map(|x| (x, y)),
+        Direction::Down => Some((x, y + 1)),
+        Direction::Right => Some((x + 1, y)),
+    }
+}
+
+fn rail_at(network: &[Vec<Rail>], position: Option<(usize, usize)>) -> Rail {
+    position.and_then(|(x, y)| network.get(y).and_then(|row| row.get(x)))
+            .cloned()
+            .unwrap_or(Rail::Empty)
+}
+
+/// The ways each piece of track could be joined up, one set of arms each.
+/// Only curves can have more than one.
+type Layouts = Vec<Vec<Vec<Vec<Direction>>>>;
+
+fn possible_arms(rail: Rail) -> Vec<Vec<Direction>> {
+    match rail {
+        Rail::Empty => vec![Vec::new()],
+        Rail::Horizontal => vec![vec![Direction::Left, Direction::Right]],
+        Rail::Vertical => vec![vec![Direction::Up, Direction::Down]],
+        Rail::Intersection => vec![DIRECTIONS.to_vec()],
+        // Each curve joins one horizontal and one vertical neighbour
+        Rail::Turn(dir) => [Direction::Right, Direction::Left].iter()
+            .map(|&horizontal| vec![horizontal, turn(opposite(horizontal), &dir)])
+            .collect(),
+    }
+}
+
+/// Works out which way round each curve is. A curve is settled once only
+/// one way round has its horizontal, or else its vertical, arm meet a
+/// neighbour that could join back. Settling a curve can settle the curves
+/// next to it, so this repeats until nothing changes.
+fn resolve(network: &[Vec<Rail>]) -> Layouts {
+    let mut layouts: Layouts = network.iter()
+        .map(|row| row.iter().map(|&rail| possible_arms(rail)).collect())
+        .collect();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for y in 0..layouts.len() {
+            for x in 0..layouts[y].len() {
+                if layouts[y][x].len() < 2 {
+                    continue;
+                }
+                for &axis in &[0, 1] {
+                    let fitting = layouts[y][x].iter()
+                        .filter(|arms| could_join(&layouts, (x, y), arms[axis]))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    if fitting.len() == 1 {
+                        layouts[y][x] = fitting;
+                        changed = true;
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    layouts
+}
+
+/// Whether the piece next to `position` in `direction` could join back to
+/// it, however it turns out to be joined up.
+fn could_join(layouts: &Layouts, position: (usize, usize), direction: Direction) -> bool {
+    neighbour(position, direction)
+        .and_then(|(x, y)| layouts.get(y).and_then(|row| row.get(x)))
+        .is_some_and(|choices| choices.iter().any(|arms| arms.contains(&opposite(direction))))
+}
+
+/// Directions the piece at `position` joins, or `None` for a curve whose
+/// neighbours don't tell which way round it is.
+fn arms(layouts: &Layouts, position: (usize, usize)) -> Option<Vec<Direction>> {
+    let (x, y) = position;
+    match layouts.get(y).and_then(|row| row.get(x)) {
+        None => Some(Vec::new()),
+        Some(choices) if choices.len() == 1 => Some(choices[0].clone()),
+        Some(_) => None,
+    }
+}
+
+/// Checks every piece of track joins up with its neighbours, and that no
+/// cart hides a curve or an intersection under it.
+fn validate(network: &[Vec<Rail>], carts: &[Cart]) -> Result<(), Vec<TrackError>> {
+    let layouts = resolve(network);
+    let mut errors = Vec::new();
+
+    // Straight track is assumed under carts, so a cart joined from the
+    // sides as well is really on a curve or an intersection
+    let mut misplaced_carts = HashSet::new();
     for cart in carts {
-        cart_pos.insert(cart.coordinate);
-        cart_heap.push(cart);
+        let joined = DIRECTIONS.iter()
+            .filter(|&&direction| joined_from(&layouts, cart.coordinate, direction))
+            .count();
+        let sideways = [turn_left(cart.direction), turn_right(cart.direction)];
+        if sideways.iter().all(|&direction| !joined_from(&layouts, cart.coordinate, direction)) {
+            continue;
+        }
+        misplaced_carts.insert(cart.coordinate);
+        errors.push(if joined == 4 {
+            TrackError::CartOnIntersection(cart.coordinate)
+        } else {
+            TrackError::CartOnCurve(cart.coordinate)
+        });
+    }
+
+    for (y, row) in network.iter().enumerate() {
+        for x in 0..row.len() {
+            if misplaced_carts.contains(&(x, y)) {
+                continue;
+            }
+            let arms = match arms(&layouts, (x, y)) {
+                Some(arms) => arms,
+                None => {
+                    errors.push(TrackError::UnresolvedCurve((x, y)));
+                    continue;
+                },
+            };
+            for direction in arms {
+                let next = neighbour((x, y), direction);
+                if next.is_some_and(|next| misplaced_carts.contains(&next)) {
+                    continue;
+                }
+                if !joined_from(&layouts, (x, y), direction) {
+                    errors.push(TrackError::Dangling((x, y), direction));
+                }
+            }
+        }
     }
 
-    (network, cart_heap, cart_pos)
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Whether the piece next to `position` in `direction` joins back to it.
+fn joined_from(layouts: &Layouts, position: (usize, usize),
+               direction: Direction) -> bool {
+    neighbour(position, direction)
+        .and_then(|next| arms(layouts, next))
+        .is_some_and(|arms| arms.contains(&opposite(direction)))
 }
 
 fn turn(old_dir: Direction, turn: &TurnDir) -> Direction {
@@ -175,10 +540,8 @@ fn turn_right(old_dir: Direction) -> Direction {
     }
 }
 
-fn intersect(cart: Cart) -> Direction {
-    let old_dir = cart.direction;
-    let next_turn = cart.next_turn;
-    match next_turn {
+fn intersect(old_dir: Direction, turn: Direction) -> Direction {
+    match turn {
         Direction::Left => turn_left(old_dir),
         Direction::Up => old_dir,
         Direction::Right => turn_right(old_dir),
@@ -186,73 +549,377 @@ fn intersect(cart: Cart) -> Direction {
     }
 }
 
-fn advance(next_turn: Direction) -> Direction {
-    match  next_turn {
-        Direction::Left => Direction::Up,
-        Direction::Up => Direction::Right,
-        Direction::Right => Direction::Left,
-        _ => panic!("Next turn can't be backwards (Down)"),
+/// What carts do at intersections, with turns given relative to the way
+/// the cart is heading: `Left`, `Up` for straight on, or `Right`.
+#[derive(Debug, Clone, PartialEq)]
+enum TurnPolicy {
+    /// Takes the turns in order, then starts over.
+    Sequence(Vec<Direction>),
+    /// Picks each turn at random, from the seed, cart and intersection.
+    Random(u64),
+}
+
+impl TurnPolicy {
+    fn standard() -> TurnPolicy {
+        TurnPolicy::Sequence(vec![Direction::Left, Direction::Up, Direction::Right])
+    }
+
+    /// A sequence of `L`, `S` and `R`, or `random:<seed>`.
+    fn parse(spec: &str) -> Result<TurnPolicy, String> {
+        if let Some(seed) = spec.strip_prefix("random:") {
+            return seed.parse()
+                       .map(TurnPolicy::Random)
+                       .map_err(|_| format!("Invalid seed: {}", seed));
+        }
+
+        let turns = spec.chars()
+                        .map(|c| match c {
+                            'L' => Ok(Direction::Left),
+                            'S' => Ok(Direction::Up),
+                            'R' => Ok(Direction::Right),
+                            _ => Err(format!("Unknown turn: {}", c)),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+        if turns.is_empty() {
+            return Err(String::from("No turns given"));
+        }
+        Ok(TurnPolicy::Sequence(turns))
+    }
+
+    /// The turn cart `id` takes at its `intersection`th intersection.
+    fn turn_at(&self, id: u32, intersection: u32) -> Direction {
+        match *self {
+            TurnPolicy::Sequence(ref turns) =>
+                turns[intersection as usize % turns.len()],
+            TurnPolicy::Random(seed) => {
+                // SplitMix64 finaliser
+                let mut z = seed ^ (u64::from(id) << 32 | u64::from(intersection));
+                z = z.wrapping_add(0x9e3779b97f4a7c15);
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                z ^= z >> 31;
+                [Direction::Left, Direction::Up, Direction::Right][(z % 3) as usize]
+            },
+        }
     }
 }
 
-fn simulate(network: &Vec<Vec<Rail>>,
-            carts: &mut BinaryHeap<Cart>,
-            cart_pos: &mut HashSet<(usize, usize)>) -> (usize, usize) {
-    loop {
-        let next_cart = carts.pop().unwrap();
+/// Two carts meeting at `position` during `tick`, the moving cart first.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+struct Collision {
+    tick: u32,
+    position: (usize, usize),
+    carts: (u32, u32),
+}
 
-        if cart_pos.contains(&next_cart.coordinate) {
-            cart_pos.remove(&next_cart.coordinate);
-        } else {
-            continue;
+/// Position of a cart at the start of each tick, ending where it crashed.
+#[derive(Debug, Clone)]
+struct Trajectory {
+    id: u32,
+    positions: Vec<(usize, usize)>,
+}
+
+/// How a run ended. `remaining` counts the carts still moving, more than
+/// one if the tick limit was reached first.
+#[derive(Debug, Clone)]
+struct Run {
+    collisions: Vec<Collision>,
+    trajectories: Vec<Trajectory>,
+    survivor: Option<Cart>,
+    remaining: usize,
+}
+
+/// Default tick limit for a run. Carts still moving by then might never
+/// crash, e.g. when they all go straight on at intersections.
+const MAX_TICKS: u32 = 100_000;
+
+/// The map of a simulation between ticks, with the id and number of
+/// intersections passed of each cart on it in reading order. The map alone
+/// can't tell what track is under the carts, so restoring also needs the
+/// network.
+#[derive(Debug, Clone, PartialEq)]
+struct Snapshot {
+    tick: u32,
+    map: String,
+    carts: Vec<(u32, u32)>,
+}
+
+impl Snapshot {
+    /// Reads back a snapshot written with `Display`.
+    fn parse(saved: &str) -> Result<Snapshot, String> {
+        let mut parts = saved.splitn(2, "\n\n");
+        let header = parts.next().unwrap();
+        let map = parts.next().ok_or_else(|| String::from("Snapshot has no map"))?;
+
+        let mut lines = header.lines();
+        let tick = lines.next()
+                        .and_then(|line| line.strip_prefix("tick "))
+                        .and_then(|tick| tick.parse().ok())
+                        .ok_or_else(|| String::from("Snapshot has no tick"))?;
+        let mut carts = Vec::new();
+        for line in lines {
+            let mut words = line.split(' ');
+            let id = words.next().and_then(|id| id.parse().ok());
+            let intersections = words.next().and_then(|count| count.parse().ok());
+            match (id, intersections) {
+                (Some(id), Some(intersections)) => carts.push((id, intersections)),
+                _ => return Err(format!("Malformed cart: {}", line)),
+            }
         }
 
-        let new_pos = match next_cart.direction {
-            Direction::Right => (next_cart.coordinate.0+1,
-                                 next_cart.coordinate.1),
-            Direction::Left  => (next_cart.coordinate.0-1,
-                                 next_cart.coordinate.1),
-            Direction::Up    => (next_cart.coordinate.0,
-                                 next_cart.coordinate.1-1),
-            Direction::Down  => (next_cart.coordinate.0,
-                                 next_cart.coordinate.1+1),
-        };
+        Ok(Snapshot { tick, map: String::from(map), carts })
+    }
+}
 
-        if cart_pos.contains(&new_pos) {
-            cart_pos.remove(&new_pos);
-            return new_pos;
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "tick {}", self.tick)?;
+        for &(id, intersections) in &self.carts {
+            writeln!(f, "{} {}", id, intersections)?;
         }
+        write!(f, "\n{}", self.map)
+    }
+}
 
-        let mut next_next_turn = next_cart.next_turn;
-        let new_dir = match network[new_pos.1][new_pos.0] {
-            Rail::Empty => panic!("Cart off track!"),
-            Rail::Horizontal | Rail::Vertical => next_cart.direction,
-            Rail::Intersection => {next_next_turn = advance(next_next_turn);
-                             intersect(next_cart)},
-            Rail::Turn(ref dir) => turn(next_cart.direction, dir),            
-        };
+/// Carts on the network, moved one at a time in the order of the heap.
+/// `occupied` maps each position to the trajectory and id of the cart
+/// there, so entries of crashed carts left in the heap are recognised and
+/// skipped, even once another cart has moved onto the same square. Carts
+/// follow their entry in `cart_policies` at intersections, or `policy` if
+/// they have none. Runs stop at `max_ticks`.
+#[derive(Debug, Clone)]
+struct Simulation {
+    network: Vec<Vec<Rail>>,
+    carts: BinaryHeap<Cart>,
+    occupied: HashMap<(usize, usize), (usize, u32)>,
+    tick: u32,
+    collisions: Vec<Collision>,
+    trajectories: Vec<Trajectory>,
+    policy: TurnPolicy,
+    cart_policies: HashMap<u32, TurnPolicy>,
+    max_ticks: u32,
+}
+
+impl Simulation {
+    fn new(network: Vec<Vec<Rail>>, carts: Vec<Cart>) -> Simulation {
+        let mut occupied = HashMap::new();
+        let mut trajectories = Vec::new();
+        for cart in &carts {
+            occupied.insert(cart.coordinate, (trajectories.len(), cart.id));
+            trajectories.push(Trajectory { id: cart.id,
+                                           positions: vec![cart.coordinate] });
+        }
+        Simulation { network, carts: carts.into_iter().collect(), occupied,
+                     tick: 0, collisions: Vec::new(), trajectories,
+                     policy: TurnPolicy::standard(), cart_policies: HashMap::new(),
+                     max_ticks: MAX_TICKS }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        let mut carts = self.live_carts().cloned().collect::<Vec<_>>();
+        carts.sort_by(|a, b| b.cmp(a));
+        Snapshot { tick: self.tick, map: render(&self.network, carts.iter()),
+                   carts: carts.iter().map(|cart| (cart.id, cart.intersections)).collect() }
+    }
+
+    /// Picks up a snapshot taken of a simulation on `network`. Collisions
+    /// and trajectories start afresh from the snapshot's tick, and turn
+    /// policies need setting again.
+    fn restore(network: Vec<Vec<Rail>>, snapshot: &Snapshot) -> Result<Simulation, String> {
+        let mut states = snapshot.carts.iter();
+        let mut carts = Vec::new();
+        for (y, line) in snapshot.map.lines().enumerate() {
+            for (x, symbol) in line.chars().enumerate() {
+                let rail = rail_at(&network, Some((x, y)));
+                match cart_direction(symbol) {
+                    Some(_) if rail == Rail::Empty =>
+                        return Err(format!("Cart at {},{} is off the track", x, y)),
+                    Some(direction) => {
+                        let &(id, intersections) = states.next()
+                            .ok_or_else(|| format!("No state for cart at {},{}", x, y))?;
+                        carts.push(Cart { coordinate: (x, y), direction, intersections,
+                                          tick: snapshot.tick, id });
+                    },
+                    None if symbol != rail_symbol(rail) =>
+                        return Err(format!("Map differs from the network at {},{}", x, y)),
+                    None => (),
+                }
+            }
+        }
+        if states.next().is_some() {
+            return Err(String::from("More cart states than carts on the map"));
+        }
 
-        cart_pos.insert(new_pos);
-        carts.push(Cart { coordinate: new_pos, direction: new_dir,
-                          tick: next_cart.tick + 1, next_turn: next_next_turn,
-                          id: next_cart.id});
+        let mut simulation = Simulation::new(network, carts);
+        simulation.tick = snapshot.tick;
+        Ok(simulation)
+    }
+
+    fn remaining(&self) -> usize {
+        self.occupied.len()
+    }
+
+    /// Carts that haven't crashed, in no particular order.
+    fn live_carts(&self) -> impl Iterator<Item = &Cart> {
+        self.carts.iter()
+            .filter(move |cart| self.occupied.get(&cart.coordinate)
+                                    .is_some_and(|&(_, id)| id == cart.id))
+    }
+
+    /// Moves every cart once, returning the collisions of that tick.
+    fn step(&mut self) -> &[Collision] {
+        let first_collision = self.collisions.len();
+        while self.carts.peek().is_some_and(|cart| cart.tick == self.tick) {
+            let next_cart = self.carts.pop().unwrap();
+            let index = match self.occupied.get(&next_cart.coordinate) {
+                Some(&(index, id)) if id == next_cart.id => index,
+                _ => continue,
+            };
+            self.occupied.remove(&next_cart.coordinate);
+
+            let new_pos = match next_cart.direction {
+                Direction::Right => (next_cart.coordinate.0+1,
+                                     next_cart.coordinate.1),
+                Direction::Left  => (next_cart.coordinate.0-1,
+                                     next_cart.coordinate.1),
+                Direction::Up    => (next_cart.coordinate.0,
+                                     next_cart.coordinate.1-1),
+                Direction::Down  => (next_cart.coordinate.0,
+                                     next_cart.coordinate.1+1),
+            };
+            self.trajectories[index].positions.push(new_pos);
+
+            if let Some((_, other)) = self.occupied.remove(&new_pos) {
+                self.collisions.push(Collision {
+                    tick: self.tick, position: new_pos,
+                    carts: (next_cart.id, other) });
+                continue;
+            }
+
+            let mut intersections = next_cart.intersections;
+            let new_dir = match self.network[new_pos.1][new_pos.0] {
+                Rail::Empty => panic!("Cart off track!"),
+                Rail::Horizontal | Rail::Vertical => next_cart.direction,
+                Rail::Intersection => {
+                    let policy = self.cart_policies.get(&next_cart.id)
+                                                   .unwrap_or(&self.policy);
+                    intersections += 1;
+                    intersect(next_cart.direction,
+                              policy.turn_at(next_cart.id, next_cart.intersections))
+                },
+                Rail::Turn(ref dir) => turn(next_cart.direction, dir),
+            };
+
+            self.occupied.insert(new_pos, (index, next_cart.id));
+            self.carts.push(Cart { coordinate: new_pos, direction: new_dir,
+                                   tick: next_cart.tick + 1, intersections,
+                                   id: next_cart.id});
+        }
+        self.tick += 1;
+        &self.collisions[first_collision..]
+    }
+
+    /// Steps until at most one cart is left, or the tick limit is reached.
+    fn run(mut self) -> Run {
+        while self.remaining() > 1 && self.tick < self.max_ticks {
+            self.step();
+        }
+        let remaining = self.remaining();
+        let survivor = if remaining == 1 { self.live_carts().next().cloned() } else { None };
+        Run { collisions: self.collisions, trajectories: self.trajectories, survivor,
+              remaining }
     }
 }
 
 fn main() {
+    // Optional arguments: --log, --save <ticks> <file>, --load <file>,
+    // --turns <policy> and --max-ticks <ticks>
+    let mut log = false;
+    let mut policy = TurnPolicy::standard();
+    let mut max_ticks = MAX_TICKS;
+    let mut save = None;
+    let mut load = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log" => log = true,
+            "--save" => {
+                let ticks: u32 = args.next()
+                                     .and_then(|ticks| ticks.parse().ok())
+                                     .expect("--save takes a number of ticks.");
+                save = Some((ticks, args.next().expect("--save takes a file.")));
+            },
+            "--load" => load = Some(args.next().expect("--load takes a file.")),
+            "--turns" => {
+                let spec = args.next().expect("--turns takes a policy.");
+                policy = TurnPolicy::parse(&spec).expect("Invalid turn policy.");
+            },
+            "--max-ticks" => {
+                max_ticks = args.next()
+                                .and_then(|ticks| ticks.parse().ok())
+                                .expect("--max-ticks takes a number of ticks.");
+            },
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
     let mut input = String::new();
     let mut f = File::open("input").expect("Failed to open input.");
     f.read_to_string(&mut input).expect("Failed to read input.");
 
-    let (network, mut carts, mut cart_pos) = parse_input(input);
-    let first_collision = simulate(&network, &mut carts, &mut cart_pos);
-    println!("First collision at {},{}", first_collision.0, first_collision.1);
+    let (network, carts) = parse_input(input);
+    if let Err(errors) = validate(&network, &carts) {
+        for error in &errors {
+            eprintln!("{}", error);
+        }
+        return;
+    }
+
+    let mut simulation = match load {
+        Some(path) => {
+            let mut saved = String::new();
+            let mut f = File::open(path).expect("Failed to open snapshot.");
+            f.read_to_string(&mut saved).expect("Failed to read snapshot.");
+            let snapshot = Snapshot::parse(&saved).expect("Failed to parse snapshot.");
+            Simulation::restore(network, &snapshot).expect("Snapshot doesn't fit the input.")
+        },
+        None => Simulation::new(network, carts),
+    };
+    simulation.policy = policy;
+    simulation.max_ticks = max_ticks;
+
+    if let Some((ticks, path)) = save {
+        let mut saving = simulation.clone();
+        while saving.tick < ticks {
+            saving.step();
+        }
+        let mut f = File::create(path).expect("Failed to create snapshot.");
+        write!(f, "{}", saving.snapshot()).expect("Failed to write snapshot.");
+    }
 
-    while cart_pos.len() > 1 {
-        simulate(&network, &mut carts, &mut cart_pos);
+    let run = simulation.run();
+    if let Some(first_collision) = run.collisions.first() {
+        println!("First collision at {},{} on tick {}", first_collision.position.0,
+                 first_collision.position.1, first_collision.tick);
     }
 
-    let last_cart = *cart_pos.iter().nth(0).unwrap();
-    println!("Last cart at {},{}", last_cart.0,
-                                   last_cart.1);
+    if let Some(last_cart) = run.survivor {
+        println!("Last cart at {},{} after {} collisions", last_cart.coordinate.0,
+                 last_cart.coordinate.1, run.collisions.len());
+    } else if run.remaining > 1 {
+        println!("{} carts still running after {} ticks", run.remaining, max_ticks);
+    }
+
+    if log {
+        for collision in &run.collisions {
+            println!("Tick {}: carts {} and {} collide at {},{}", collision.tick,
+                     collision.carts.0, collision.carts.1,
+                     collision.position.0, collision.position.1);
+        }
+        for trajectory in &run.trajectories {
+            let end = trajectory.positions.last().unwrap();
+            println!("Cart {}: {} moves, ending at {},{}", trajectory.id,
+                     trajectory.positions.len() - 1, end.0, end.1);
+        }
+    }
 }
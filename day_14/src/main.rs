@@ -4,100 +4,242 @@ mod tests {
 
     #[test]
     fn test_first_half() {
-        assert_eq!(run_simulation(5, false), 0124515891u64);
-        assert_eq!(run_simulation(9, false), 5158916779u64);
-        assert_eq!(run_simulation(18, false), 9251071085u64);
-        assert_eq!(run_simulation(2018, false), 5941429882u64);
+        assert_eq!(run_simulation(5), 124515891u64);
+        assert_eq!(run_simulation(9), 5158916779u64);
+        assert_eq!(run_simulation(18), 9251071085u64);
+        assert_eq!(run_simulation(2018), 5941429882u64);
     }
 
     #[test]
     fn test_second_half() {
-        assert_eq!(run_simulation(515891, true), 9);
-        assert_eq!(run_simulation(012451, true), 5);
-        assert_eq!(run_simulation(925107, true), 18);
-        assert_eq!(run_simulation(594142, true), 2018);
+        assert_eq!(find_pattern("515891", MAX_RECIPES), Ok(9));
+        assert_eq!(find_pattern("01245", MAX_RECIPES), Ok(5));
+        assert_eq!(find_pattern("012451", MAX_RECIPES), Ok(5));
+        assert_eq!(find_pattern("925107", MAX_RECIPES), Ok(18));
+        assert_eq!(find_pattern("59414", MAX_RECIPES), Ok(2018));
+        assert_eq!(find_pattern("594142", MAX_RECIPES), Ok(2018));
     }
-}
 
-fn check_recipes(recipes: &Vec<usize>, input: usize) -> u64 {
-    let end = recipes.len() - 1;
-    if end < 7 {
-        return 0;
+    #[test]
+    fn test_patterns() {
+        // Recipes start 3710101245158916
+        assert_eq!(find_pattern("3", MAX_RECIPES), Ok(0));
+        assert_eq!(find_pattern("37", MAX_RECIPES), Ok(0));
+        assert_eq!(find_pattern("0", MAX_RECIPES), Ok(3));
+        assert_eq!(find_pattern("1010", MAX_RECIPES), Ok(2));
+        assert_eq!(find_pattern("3710101245158916", MAX_RECIPES), Ok(0));
+        assert!(find_pattern("", MAX_RECIPES).is_err());
+        assert!(find_pattern("12a", MAX_RECIPES).is_err());
+        assert_eq!(find_pattern("0", 4), Ok(3));
+        assert!(find_pattern("0", 3).is_err());
+
+        // "10" ends on the first of the two digits 1 and 0 added together
+        let mut matcher = Matcher::new("10").unwrap();
+        assert_eq!([3, 7, 1].iter().map(|&d| matcher.push(d)).collect::<Vec<_>>(),
+                   vec![false, false, false]);
+        assert!(matcher.push(0));
+        assert!(!matcher.push(0));
+        assert!(!matcher.push(1));
+        assert!(matcher.push(0));
     }
-    if recipes[end] == input % 10 {
-        let mut sum = 0;
-        let mut coeff = 1;
-        for i in 0..6 {
-            sum += recipes[end - i] * coeff;
-            coeff *= 10;
+
+    // The elves as first written, with any number of them
+    fn naive_recipes(scores: &[u8], num_elves: usize, count: usize) -> Vec<u8> {
+        let mut recipes: Vec<usize> = scores.iter().map(|&score| score as usize).collect();
+        let mut elves: Vec<usize> = (0..num_elves).collect();
+        while recipes.len() < count {
+            let sum: usize = elves.iter().map(|&elf| recipes[elf]).sum();
+            recipes.extend(sum.to_string().bytes().map(|b| (b - b'0') as usize));
+            for elf in &mut elves {
+                *elf = (*elf + recipes[*elf] + 1) % recipes.len();
+            }
         }
-        if sum == input {
-            return end as u64 - 5;
+        recipes.iter().take(count).map(|&recipe| recipe as u8).collect()
+    }
+
+    #[test]
+    fn test_scoreboard() {
+        assert_eq!(Scoreboard::standard().take(10).collect::<Vec<_>>(),
+                   vec![3, 7, 1, 0, 1, 0, 1, 2, 4, 5]);
+        assert_eq!(Scoreboard::standard().digits_after(2018, 10),
+                   &[5, 9, 4, 1, 4, 2, 9, 8, 8, 2]);
+
+        for &(scores, num_elves) in &[(&[3, 7][..], 1), (&[9, 9, 9][..], 3),
+                                      (&[0, 0][..], 2), (&[1, 2, 3, 4][..], 4)] {
+            let mut scoreboard = Scoreboard::new(scores, num_elves).unwrap();
+            let expected = naive_recipes(scores, num_elves, 501);
+            assert_eq!(scoreboard.by_ref().take(500).collect::<Vec<_>>(), &expected[..500]);
+
+            // Searching starts from the first recipe, wherever iteration got to
+            let wanted = &expected[300..306];
+            let pattern: String = wanted.iter().map(|d| d.to_string()).collect();
+            let first = (0..expected.len()).find(|&i| expected[i..].starts_with(wanted));
+            assert_eq!(scoreboard.find(&pattern, expected.len()).ok(), first);
+            assert_eq!(scoreboard.next(), Some(expected[500]));
         }
+
+        assert_eq!(Scoreboard::standard().find("51589", 14), Ok(9));
+        assert!(Scoreboard::standard().find("51589", 13).is_err());
+        assert!(Scoreboard::new(&[0, 0], 2).unwrap().find("1", 1000).is_err());
+
+        assert!(Scoreboard::new(&[3, 7], 3).is_err());
+        assert!(Scoreboard::new(&[3, 7], 0).is_err());
+        assert!(Scoreboard::new(&[3, 17], 2).is_err());
     }
+}
+
+/// Knuth-Morris-Pratt matcher, fed one digit at a time.
+#[derive(Debug, Clone)]
+struct Matcher {
+    pattern: Vec<u8>,
+    // Length of the longest proper prefix of `pattern[..=i]` that is also
+    // a suffix of it
+    failure: Vec<usize>,
+    matched: usize,
+}
 
-    if recipes[end - 1] == input % 10 {
-        let mut sum = 0;
-        let mut coeff = 1;
-        for i in 0..6 {
-            sum += recipes[end - 1 - i] * coeff;
-            coeff *= 10;
+impl Matcher {
+    fn new(pattern: &str) -> Result<Matcher, String> {
+        let pattern = pattern.chars()
+                             .map(|c| c.to_digit(10)
+                                       .map(|digit| digit as u8)
+                                       .ok_or_else(|| format!("Not a digit: {}", c)))
+                             .collect::<Result<Vec<u8>, String>>()?;
+        if pattern.is_empty() {
+            return Err(String::from("Empty pattern"));
         }
-        if sum == input {
-            return end as u64 - 6;
+
+        let mut failure = vec![0; pattern.len()];
+        let mut length = 0;
+        for i in 1..pattern.len() {
+            while length > 0 && pattern[i] != pattern[length] {
+                length = failure[length - 1];
+            }
+            if pattern[i] == pattern[length] {
+                length += 1;
+            }
+            failure[i] = length;
         }
 
+        Ok(Matcher { pattern, failure, matched: 0 })
+    }
+
+    /// Whether the pattern ends with this digit.
+    fn push(&mut self, digit: u8) -> bool {
+        if self.matched == self.pattern.len() {
+            self.matched = self.failure[self.matched - 1];
+        }
+        while self.matched > 0 && self.pattern[self.matched] != digit {
+            self.matched = self.failure[self.matched - 1];
+        }
+        if self.pattern[self.matched] == digit {
+            self.matched += 1;
+        }
+        self.matched == self.pattern.len()
     }
+}
 
-    0
+/// Recipes made so far, and each elf's current recipe. Iterating yields
+/// every recipe's score in order, making more recipes as needed.
+#[derive(Debug, Clone)]
+struct Scoreboard {
+    recipes: Vec<u8>,
+    elves: Vec<usize>,
+    next: usize,
 }
 
-fn run_simulation(input: usize, find_occurrence: bool) -> u64 {
-    let mut recipes = vec![3, 7];
-    let mut elves = vec![0, 1];
-    loop {
-        let mut sum = 0;
-        for elf in &elves {
-            sum += recipes[*elf];
+impl Scoreboard {
+    /// Elf `i` starts on recipe `i`.
+    fn new(scores: &[u8], num_elves: usize) -> Result<Scoreboard, String> {
+        if let Some(score) = scores.iter().find(|&&score| score > 9) {
+            return Err(format!("Score isn't a digit: {}", score));
         }
+        if num_elves == 0 || num_elves > scores.len() {
+            return Err(format!("Can't place {} elves on {} recipes",
+                               num_elves, scores.len()));
+        }
+        Ok(Scoreboard { recipes: scores.to_vec(), elves: (0..num_elves).collect(),
+                        next: 0 })
+    }
 
-        let mut new_recipes = Vec::new();
+    fn standard() -> Scoreboard {
+        Scoreboard::new(&[3, 7], 2).unwrap()
+    }
+
+    fn step(&mut self) {
+        let sum: usize = self.elves.iter()
+                             .map(|&elf| self.recipes[elf] as usize)
+                             .sum();
 
-        if sum == 0 {
-            new_recipes.push(0);
+        let mut power = 1;
+        while power * 10 <= sum {
+            power *= 10;
         }
-        while sum > 0 {
-            new_recipes.push(sum % 10);
-            sum /= 10;
+        while power > 0 {
+            self.recipes.push((sum / power % 10) as u8);
+            power /= 10;
         }
 
-        while !new_recipes.is_empty() {
-            recipes.push(new_recipes.pop().unwrap());
+        for elf in &mut self.elves {
+            *elf = (*elf + self.recipes[*elf] as usize + 1) % self.recipes.len();
         }
+    }
 
-        for elf in &mut elves {
-            *elf = (*elf + recipes[*elf] + 1) % recipes.len()
+    fn recipe(&mut self, index: usize) -> u8 {
+        while self.recipes.len() <= index {
+            self.step();
         }
+        self.recipes[index]
+    }
 
-        if   !find_occurrence && recipes.len() >= input + 10
-           || find_occurrence && check_recipes(&recipes, input) != 0 {
-            break;
+    /// Scores of the `count` recipes after the first `skip`.
+    fn digits_after(&mut self, skip: usize, count: usize) -> &[u8] {
+        if count > 0 {
+            self.recipe(skip + count - 1);
         }
+        &self.recipes[skip..skip + count]
     }
 
-    if !find_occurrence {
-        // Output last 10 recipes
-        let mut ans: u64 = 0;
-        for i in 0..10 {
-            ans = ans*10 + recipes[input + i] as u64;
+    /// Number of recipes before `pattern`, a string of digits, first appears.
+    /// Fails if it doesn't appear within the first `limit` recipes.
+    fn find(&mut self, pattern: &str, limit: usize) -> Result<usize, String> {
+        let mut matcher = Matcher::new(pattern)?;
+        for index in 0..limit {
+            if matcher.push(self.recipe(index)) {
+                return Ok(index + 1 - matcher.pattern.len());
+            }
         }
-        ans
-    } else {
-        check_recipes(&recipes, input)
+        Err(format!("{} not found in the first {} recipes", pattern, limit))
     }
 }
 
+impl Iterator for Scoreboard {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        let score = self.recipe(self.next);
+        self.next += 1;
+        Some(score)
+    }
+}
+
+fn run_simulation(input: usize) -> u64 {
+    // Output the 10 recipes after the first `input`
+    Scoreboard::standard().digits_after(input, 10)
+                          .iter()
+                          .fold(0, |ans, &score| ans*10 + u64::from(score))
+}
+
+/// Recipes searched for a pattern unless told otherwise, a byte each.
+const MAX_RECIPES: usize = 1_000_000_000;
+
+/// Number of recipes before `pattern`, a string of digits, first appears
+/// within the first `limit` recipes.
+fn find_pattern(pattern: &str, limit: usize) -> Result<u64, String> {
+    Scoreboard::standard().find(pattern, limit).map(|index| index as u64)
+}
+
 fn main() {
-    println!("Score: {}", run_simulation(360781, false));
-    println!("At: {}", run_simulation(360781, true));
+    println!("Score: {}", run_simulation(360781));
+    println!("At: {}", find_pattern("360781", MAX_RECIPES).expect("Invalid pattern."));
 }
@@ -1,8 +1,9 @@
 use std::io::prelude::*;
 use std::fs::File;
-use std::sync::atomic::{Ordering, AtomicUsize};
-use std::collections::{HashMap, HashSet, VecDeque};
-
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::env;
+use std::fmt;
+use std::thread;
 
 #[cfg(test)]
 mod tests {
@@ -78,42 +79,147 @@ mod tests {
     fn test_second_half() {
         test_power(TEST_INPUT_1, (15, 29, 172));
         test_power(TEST_INPUT_3, (4,  33, 948));
-        //test_power(TEST_INPUT_4, (15, 37, 94));
+        test_power(TEST_INPUT_4, (15, 37, 94));
         test_power(TEST_INPUT_5, (12, 39, 166));
         test_power(TEST_INPUT_6, (34, 30, 38));
     }
 
     fn test_power(input: &str, expected: (i32, u32, i32)) {
         let input = String::from(input);
-        let (walls, actors) = parse_input(input);
-        let power = find_winning_power(&walls, &actors);
-        assert_eq!(power, expected);
+        let roster = Roster::standard();
+        let board = parse_input(input, &roster);
+        let winner = search_power(&board, &roster, ELVES, 4).winner;
+        assert_eq!((winner.attack, winner.turn, winner.total_hp), expected);
     }
 
     fn test_resolution(input: &str, expected: (u32, i32)) {
         let input = String::from(input);
-        let (walls, mut actors) = parse_input(input);
-        assert_eq!(run(&walls, &mut actors, 3), expected);
+        let roster = Roster::standard();
+        let mut board = parse_input(input, &roster);
+        assert_eq!(run(&mut board, &roster), expected);
     }
 
     #[test]
-    fn test_pos_comparison() {
-        assert!(GridPos {y: 0, x: 0} < GridPos {y: 0, x: 1});
-        assert!(GridPos {y: 0, x: 0} < GridPos {y: 1, x: 0});
-        assert!(GridPos {y: 0, x: 1} < GridPos {y: 1, x: 0});
+    fn test_combat_log() {
+        let roster = Roster::standard();
+        let mut board = parse_input(String::from(TEST_INPUT_1), &roster);
+        let initial = board.clone();
+        let (turn, total_hp, log) = run_logged(&mut board, &roster);
+        assert_eq!((turn, total_hp), (47, 590));
+
+        assert_eq!(log.boards.len(), 48);
+        assert_eq!(log.boards[1], "#######\n\
+                                   #..G..#   G(200)\n\
+                                   #...EG#   E(197), G(197)\n\
+                                   #.#G#G#   G(200), G(197)\n\
+                                   #...#E#   E(197)\n\
+                                   #.....#\n\
+                                   #######\n");
+        assert_eq!(log.boards[47], "#######\n\
+                                    #G....#   G(200)\n\
+                                    #.G...#   G(131)\n\
+                                    #.#.#G#   G(59)\n\
+                                    #...#.#\n\
+                                    #....G#   G(200)\n\
+                                    #######\n");
+
+        let id_at = |x, y| initial.actors[&GridPos { x, y }].uid;
+        assert_eq!(log.events[0], Event::Move { round: 1, actor: id_at(2, 1),
+                                                from: GridPos { x: 2, y: 1 },
+                                                to: GridPos { x: 3, y: 1 } });
+        assert_eq!(log.events[1], Event::Attack { round: 1, attacker: id_at(4, 2),
+                                                  target: id_at(5, 2), damage: 3,
+                                                  hp_left: 197 });
+        let deaths = log.events.iter()
+                               .filter_map(|event| match *event {
+                                   Event::Death { round, actor, .. } => Some((round, actor)),
+                                   _ => None,
+                               })
+                               .collect::<Vec<_>>();
+        assert_eq!(deaths.len(), 2);
+        assert!(deaths.iter().all(|&(_, actor)| initial.actor(actor).unwrap().faction == ELVES));
+
+        assert_eq!(log.summary,
+                   vec![SideSummary { faction: ELVES, units: 2, survivors: 0, hp_left: 0,
+                                      damage_dealt: 210 },
+                        SideSummary { faction: GOBLINS, units: 4, survivors: 4, hp_left: 590,
+                                      damage_dealt: 402 }]);
     }
 
-}
+    #[test]
+    fn test_factions() {
+        // Dwarves side with the elves, and hit hard but can't take much
+        let mut roster = Roster::standard();
+        let dwarves = roster.add_faction(Faction { name: String::from("Dwarves"),
+                                                   symbol: 'D', hp: 50, attack: 10 });
+        roster.set_hostile(ELVES, dwarves, false);
+        assert!(roster.hostile(GOBLINS, dwarves) && roster.hostile(dwarves, GOBLINS));
+        assert!(!roster.hostile(dwarves, ELVES) && !roster.hostile(ELVES, ELVES));
+        assert_eq!(roster.faction_of('D'), Some(dwarves));
+        assert_eq!(roster.faction_of('x'), None);
+
+        let mut board = parse_input(String::from("#####\n#EGD#\n#####"), &roster);
+        assert_eq!(board.total_hp(), 450);
+        // Each round the elf and the dwarf do 13 damage, and the goblin hits
+        // the elf, being first in reading order, until dying in round 16
+        assert_eq!(run(&mut board, &roster), (16, 152 + 50));
+
+        let mut board = parse_input(String::from("#####\n#E.D#\n#####"), &roster);
+        assert_eq!(run(&mut board, &roster), (0, 250));
+
+        // Everyone against everyone
+        let mut roster = Roster::standard();
+        roster.add_faction(Faction { name: String::from("Dwarves"), symbol: 'D',
+                                     hp: 50, attack: 10 });
+        let mut board = parse_input(String::from("#####\n#EGD#\n#####"), &roster);
+        let (_, _, log) = run_logged(&mut board, &roster);
+        assert_eq!(log.summary.iter().map(|side| side.survivors).collect::<Vec<_>>(),
+                   vec![1, 0, 0]);
+    }
 
-#[derive(Eq, PartialEq, PartialOrd, Copy, Clone)]
-struct Distance {
-    val: usize,
-}
+    #[test]
+    fn test_deterministic_ids() {
+        let roster = Roster::standard();
+        let first = parse_input(String::from(TEST_INPUT_5), &roster);
+        let second = parse_input(String::from(TEST_INPUT_5), &roster);
+        assert_eq!(first, second);
+        assert!(first.actors.values().map(|a| a.uid).eq(0..first.actors.len()));
+
+        let (mut first, mut second) = (first, second);
+        let (first_turn, first_hp, first_log) = run_logged(&mut first, &roster);
+        let (second_turn, second_hp, second_log) = run_logged(&mut second, &roster);
+        assert_eq!((first_turn, first_hp), (second_turn, second_hp));
+        assert_eq!(first_log.events, second_log.events);
+        assert_eq!(first_log.boards, second_log.boards);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_power_search() {
+        let roster = Roster::standard();
+        for &input in &[TEST_INPUT_1, TEST_INPUT_3, TEST_INPUT_6] {
+            let board = parse_input(String::from(input), &roster);
+            let linear = (3..).map(|attack| trial(&board, &roster, ELVES, attack))
+                              .find(|trial| trial.deaths == 0)
+                              .unwrap();
+            for &threads in &[1, 3, 8] {
+                let search = search_power(&board, &roster, ELVES, threads);
+                assert_eq!(search.winner, linear);
+                assert!(search.monotone);
+                assert!(search.trials.windows(2).all(|pair| pair[0].attack < pair[1].attack));
+                for tried in &search.trials {
+                    assert_eq!(*tried, trial(&board, &roster, ELVES, tried.attack));
+                    assert_eq!(tried.deaths == 0, tried.attack >= linear.attack);
+                }
+            }
+        }
+    }
 
-impl Ord for Distance {
-    fn cmp(&self, other: &Distance) -> std::cmp::Ordering {
-        // Reverse order to make min heap
-        other.val.cmp(&self.val)
+    #[test]
+    fn test_pos_comparison() {
+        assert!(GridPos {y: 0, x: 0} < GridPos {y: 0, x: 1});
+        assert!(GridPos {y: 0, x: 0} < GridPos {y: 1, x: 0});
+        assert!(GridPos {y: 0, x: 1} < GridPos {y: 1, x: 0});
     }
 
 }
@@ -126,9 +232,7 @@ struct GridPos {
 
 impl GridPos {
     fn distance(self, other: GridPos) -> usize {
-        let x_diff = (self.x as isize - other.x as isize).abs() as usize;
-        let y_diff = (self.y as isize - other.y as isize).abs() as usize;
-        x_diff + y_diff
+        self.x.abs_diff(other.x) + self.y.abs_diff(other.y)
     }
 
     fn is_adjacent(self, other: GridPos) -> bool {
@@ -146,280 +250,558 @@ impl GridPos {
             
     }
 
-    fn pathfind(self, other: GridPos, walls: &Vec<Vec<bool>>,
-                units: &HashMap<GridPos, bool>) -> GridPos {
-        // Dijkstra's since I'm feeling lazy
-        let mut frontier = VecDeque::new();
-        let mut explored = HashSet::new();
-
-        for i in 0..4 {
-            let step = self.adjacent(i);
-            if walls[step.y][step.x]
-               || units.contains_key(&step) {
-                continue;
-            }
+    fn neighbours(self) -> impl Iterator<Item = GridPos> {
+        (0..4).map(move |direction| self.adjacent(direction))
+    }
+}
 
-            if step == other {
-                return step;
-            }
+/// What a faction's units start with, and how they're shown on the map.
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Faction {
+    name: String,
+    symbol: char,
+    hp: i32,
+    attack: i32,
+}
 
-            frontier.push_back((Distance{val: 0}, step, step));
-        }
+/// The factions in a battle, and which of them fight each other.
+/// Factions are hostile to all others unless made peaceful.
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Roster {
+    factions: Vec<Faction>,
+    hostility: Vec<Vec<bool>>,
+}
 
-        if frontier.len() == 1 {
-            return frontier.pop_front().unwrap().2;
-        }
+const ELVES: usize = 0;
+const GOBLINS: usize = 1;
+
+impl Roster {
+    /// Elves and goblins, with 200 HP and 3 attack each.
+    fn standard() -> Roster {
+        let mut roster = Roster { factions: Vec::new(), hostility: Vec::new() };
+        let elves = roster.add_faction(Faction { name: String::from("Elves"), symbol: 'E',
+                                                 hp: 200, attack: 3 });
+        let goblins = roster.add_faction(Faction { name: String::from("Goblins"),
+                                                   symbol: 'G', hp: 200, attack: 3 });
+        assert!(elves == ELVES && goblins == GOBLINS);
+        roster
+    }
 
-        while !frontier.is_empty() {
-            let (distance, next_step, first_step) = frontier.pop_front().unwrap();
-            let distance = distance.val + 1;
-            for i in 0..4 {
-                let adj = next_step.adjacent(i);
-                if adj == other {
-                    return first_step;
-                }
-                if walls[adj.y][adj.x] ||
-                   units.contains_key(&adj) ||
-                   explored.contains(&adj) {
-                    continue;
-                }
-                explored.insert(adj);
-                frontier.push_back( (Distance {val: distance}, adj, first_step) );
-            }
+    fn add_faction(&mut self, faction: Faction) -> usize {
+        assert!(self.faction_of(faction.symbol).is_none() && faction.symbol != '#'
+                    && faction.symbol != '.',
+                "Symbol already taken: {}", faction.symbol);
+        let index = self.factions.len();
+        for row in &mut self.hostility {
+            row.push(true);
         }
-
-        return self;
+        self.hostility.push((0..=index).map(|other| other != index).collect());
+        self.factions.push(faction);
+        index
     }
 
-    fn nearest_enemy(self, is_elf: bool, walls: &Vec<Vec<bool>>,
-                     units: &HashMap<GridPos, bool>) -> GridPos {
-        // Dijkstra's since I'm feeling lazy
-        let mut frontier = VecDeque::new();
-        let mut explored = HashSet::new();
-
-        frontier.push_back((Distance{val: 0}, self));
-
-        let mut finish_distance = std::usize::MAX;
-        let mut target = self;
-        while !frontier.is_empty() {
-            let (distance, next_step) = frontier.pop_front().unwrap();
-
-            if distance.val >= finish_distance {
-                break;
-            }
-
-            let distance = distance.val + 1;
-            for i in 0..4 {
-                let adj = next_step.adjacent(i);
-
-                if units.contains_key(&adj) {
-                    if units[&adj] != is_elf &&
-                       (distance < finish_distance || adj < target) {
-                        target = adj;
-                        finish_distance = distance;
-                    }
-                    continue;
-                }
+    fn set_hostile(&mut self, a: usize, b: usize, hostile: bool) {
+        self.hostility[a][b] = hostile;
+        self.hostility[b][a] = hostile;
+    }
 
-                if walls[adj.y][adj.x] ||
-                   explored.contains(&adj) {
-                    continue;
-                }
-                explored.insert(adj);
-                frontier.push_back( (Distance {val: distance}, adj) );
-            }
-        }
+    fn hostile(&self, a: usize, b: usize) -> bool {
+        self.hostility[a][b]
+    }
 
-        target
+    fn faction_of(&self, symbol: char) -> Option<usize> {
+        self.factions.iter().position(|faction| faction.symbol == symbol)
     }
 }
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 struct Actor {
     uid: usize,
-    is_elf: bool,
+    faction: usize,
     hp: i32,
     pos: GridPos,
-    turn: u32,
 }
 
 impl Actor {
-    fn new(is_elf: bool, x: usize, y: usize) -> Actor {
-        static NUM_ACTORS: AtomicUsize = AtomicUsize::new(0);
-        Actor {uid: NUM_ACTORS.fetch_add(1, Ordering::Relaxed),
-               is_elf: is_elf,
-               hp: 200,
-               pos: GridPos{x: x, y: y},
-               turn: 0}
+    fn new(uid: usize, faction: usize, hp: i32, x: usize, y: usize) -> Actor {
+        Actor {uid,
+               faction,
+               hp,
+               pos: GridPos{x, y}}
     }
 }
 
-fn should_end_combat(actors: &HashMap<usize, Actor>) -> bool {
-    let mut found_elf = false;
-    let mut found_goblin = false;
-    for (_, actor) in actors {
-        found_elf |= actor.is_elf;
-        found_goblin |= !actor.is_elf;
-        if found_goblin && found_elf {
-            break;
-        }
-    }
-    !(found_goblin && found_elf)
+/// The cave and the units in it, kept by position so they come out in
+/// reading order. Units are numbered in reading order when parsed.
+#[derive(Debug, Eq, PartialEq, Clone)]
+struct Board {
+    walls: Vec<Vec<bool>>,
+    actors: BTreeMap<GridPos, Actor>,
 }
 
-fn next_step(actor: usize, actors: &mut HashMap<usize, Actor>,
-             walls: &Vec<Vec<bool>>) -> GridPos {;
-    let start: GridPos = actors[&actor].pos;
-    let is_elf = actors[&actor].is_elf;
-    let mut actor_pos = HashMap::new();
+impl Board {
+    fn is_open(&self, pos: GridPos) -> bool {
+        !self.walls[pos.y][pos.x] && !self.actors.contains_key(&pos)
+    }
+
+    fn actor(&self, uid: usize) -> Option<&Actor> {
+        self.actors.values().find(|actor| actor.uid == uid)
+    }
+
+    fn count_faction(&self, faction: usize) -> usize {
+        self.actors.values().filter(|a| a.faction == faction).count()
+    }
+
+    fn total_hp(&self) -> i32 {
+        self.actors.values().map(|a| a.hp).sum()
+    }
+
+    fn should_end_combat(&self, roster: &Roster) -> bool {
+        let factions: HashSet<usize> = self.actors.values().map(|a| a.faction).collect();
+        !factions.iter().any(|&a| factions.iter().any(|&b| roster.hostile(a, b)))
+    }
+
+    /// Steps to every open square reachable from `start`.
+    fn distances_from(&self, start: GridPos) -> HashMap<GridPos, usize> {
+        let mut distances = HashMap::new();
+        let mut frontier = VecDeque::new();
+        distances.insert(start, 0);
+        frontier.push_back(start);
+        while let Some(pos) = frontier.pop_front() {
+            let distance = distances[&pos] + 1;
+            for adj in pos.neighbours() {
+                if self.is_open(adj) && !distances.contains_key(&adj) {
+                    distances.insert(adj, distance);
+                    frontier.push_back(adj);
+                }
+            }
+        }
+        distances
+    }
 
-    for (_, actor) in actors.iter() {
-        if actor.is_elf != is_elf && start.is_adjacent(actor.pos) {
+    /// Where the unit at `start` moves: one step towards the nearest open
+    /// square next to an enemy, along the shortest path, breaking ties in
+    /// reading order both times.
+    fn next_step(&self, start: GridPos, roster: &Roster) -> GridPos {
+        let faction = self.actors[&start].faction;
+        let enemies: Vec<GridPos> = self.actors.values()
+                                        .filter(|a| roster.hostile(faction, a.faction))
+                                        .map(|a| a.pos)
+                                        .collect();
+        if enemies.iter().any(|&enemy| start.is_adjacent(enemy)) {
             return start;
         }
-        actor_pos.insert(actor.pos, actor.is_elf);
+
+        let distances = self.distances_from(start);
+        let target = enemies.iter()
+                            .flat_map(|enemy| enemy.neighbours())
+                            .filter_map(|pos| distances.get(&pos).map(|&d| (d, pos)))
+                            .min();
+        let target = match target {
+            Some((_, target)) => target,
+            // No accessible targets found
+            None => return start,
+        };
+
+        let back = self.distances_from(target);
+        start.neighbours()
+             .filter_map(|pos| back.get(&pos).map(|&d| (d, pos)))
+             .min()
+             .map_or(start, |(_, pos)| pos)
     }
+}
 
-    let closest = start.nearest_enemy(is_elf, &walls, &actor_pos);
+/// Something that happened during `round`, counting from 1.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum Event {
+    Move { round: u32, actor: usize, from: GridPos, to: GridPos },
+    Attack { round: u32, attacker: usize, target: usize, damage: i32, hp_left: i32 },
+    Death { round: u32, actor: usize, pos: GridPos },
+}
 
-    if closest == start {
-        // No accessible targets found
-        return start;
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Move { round, actor, from, to } =>
+                write!(f, "Round {}: {} moves from {},{} to {},{}", round, actor,
+                       from.x, from.y, to.x, to.y),
+            Event::Attack { round, attacker, target, damage, hp_left } =>
+                write!(f, "Round {}: {} hits {} for {}, leaving {} HP", round,
+                       attacker, target, damage, hp_left),
+            Event::Death { round, actor, pos } =>
+                write!(f, "Round {}: {} dies at {},{}", round, actor, pos.x, pos.y),
+        }
     }
+}
 
-    start.pathfind(closest, walls, &actor_pos)
+/// How one side fared over a whole battle.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+struct SideSummary {
+    faction: usize,
+    units: usize,
+    survivors: usize,
+    hp_left: i32,
+    damage_dealt: i32,
 }
 
-fn run(walls: &Vec<Vec<bool>>, actors: &mut HashMap<usize, Actor>,
-       elf_attack: i32)
-    -> (u32, i32) {
-    let mut turn = 0;
+/// Everything that happened in a battle. `boards[r]` is the board after
+/// `r` full rounds, with each row's units and their HP alongside.
+#[derive(Debug, Clone, Default)]
+struct CombatLog {
+    events: Vec<Event>,
+    boards: Vec<String>,
+    summary: Vec<SideSummary>,
+    factions: Vec<String>,
+}
+
+impl fmt::Display for CombatLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{}", event)?;
+        }
+        for side in &self.summary {
+            writeln!(f, "{}: {} of {} left with {} HP, dealt {} damage",
+                     self.factions[side.faction], side.survivors,
+                     side.units, side.hp_left, side.damage_dealt)?;
+        }
+        Ok(())
+    }
+}
 
-    let mut skip_movement = false;
-    let mut last_actors = HashSet::new();
-    loop {
+fn render(board: &Board, roster: &Roster) -> String {
+    let mut rendered = String::new();
+    for (y, row) in board.walls.iter().enumerate() {
+        let mut units = Vec::new();
+        for (x, &wall) in row.iter().enumerate() {
+            match board.actors.get(&GridPos { x, y }) {
+                Some(actor) => {
+                    let symbol = roster.factions[actor.faction].symbol;
+                    rendered.push(symbol);
+                    units.push(format!("{}({})", symbol, actor.hp));
+                },
+                None => rendered.push(if wall { '#' } else { '.' }),
+            }
+        }
+        if !units.is_empty() {
+            rendered.push_str("   ");
+            rendered.push_str(&units.join(", "));
+        }
+        rendered.push('\n');
+    }
+    rendered
+}
 
-        let actor;
-        {
-            let next_actor = actors.iter()
-                                   .filter(|(_, a)| a.turn == turn)
-                                   .map(|(id, a)| (a.pos, id))
-                                   .min();
+fn run(board: &mut Board, roster: &Roster) -> (u32, i32) {
+    battle(board, roster, None)
+}
 
-            if next_actor == None {
-                turn += 1;
+fn run_logged(board: &mut Board, roster: &Roster) -> (u32, i32, CombatLog) {
+    let names = roster.factions.iter().map(|faction| faction.name.clone()).collect();
+    let mut log = CombatLog { factions: names, ..CombatLog::default() };
+    let (turn, total_hp) = battle(board, roster, Some(&mut log));
+    (turn, total_hp, log)
+}
 
-                let mut actor_pos = HashSet::new();
-                for (_, actor) in actors.iter() {
-                    actor_pos.insert( (actor.pos, actor.is_elf) );
-                }
-                skip_movement = last_actors == actor_pos;
-                last_actors = actor_pos;
+fn summarise(initial: &Board, survivors: &Board, events: &[Event],
+             roster: &Roster) -> Vec<SideSummary> {
+    (0..roster.factions.len()).map(|faction| {
+        let damage_dealt = events.iter()
+            .map(|event| match *event {
+                Event::Attack { attacker, damage, .. }
+                    if initial.actor(attacker).unwrap().faction == faction => damage,
+                _ => 0,
+            })
+            .sum();
+        let hp_left = survivors.actors.values()
+                               .filter(|a| a.faction == faction)
+                               .map(|a| a.hp)
+                               .sum();
+        SideSummary { faction, units: initial.count_faction(faction),
+                      survivors: survivors.count_faction(faction),
+                      hp_left, damage_dealt }
+    }).collect()
+}
+
+fn battle(board: &mut Board, roster: &Roster,
+          mut log: Option<&mut CombatLog>) -> (u32, i32) {
+    let initial = board.clone();
+    if let Some(log) = log.as_deref_mut() {
+        log.boards.push(render(board, roster));
+    }
 
+    let mut turn = 0;
+    'combat: loop {
+        // Units take turns in reading order as of the start of the round,
+        // skipping any killed before their turn comes
+        let order: Vec<(GridPos, usize)> = board.actors.iter()
+                                                .map(|(&pos, a)| (pos, a.uid))
+                                                .collect();
+        for (old_pos, actor) in order {
+            if board.actors.get(&old_pos).map(|a| a.uid) != Some(actor) {
                 continue;
             }
 
-            actor = *next_actor.unwrap().1;
-        }
-
-        if should_end_combat(actors) {
-            break;
-        }
+            if board.should_end_combat(roster) {
+                break 'combat;
+            }
 
-        let new_pos =
-            if !skip_movement { next_step(actor, actors, walls) }
-            else              { actors[&actor].pos };
-        actors.entry(actor).and_modify(|a| a.pos = new_pos);
-        actors.entry(actor).and_modify(|a| a.turn += 1);
-        let elf_attacker = actors[&actor].is_elf;
-
-        let target = {
-            let target_actor = actors.iter()
-                .filter(|(_, a)| new_pos.is_adjacent(a.pos)
-                                && (a.is_elf != elf_attacker))
-                                   .map(|(id, a)| (a.hp, a.pos, id))
-                                   .min();
-            match target_actor {
-                None => None,
-                Some((_, _, id)) => Some(*id),
+            let new_pos = board.next_step(old_pos, roster);
+            let mut unit = board.actors.remove(&old_pos).unwrap();
+            unit.pos = new_pos;
+            board.actors.insert(new_pos, unit);
+            let attacker = unit.faction;
+            if let Some(log) = log.as_deref_mut() {
+                if new_pos != old_pos {
+                    log.events.push(Event::Move { round: turn + 1, actor,
+                                                  from: old_pos, to: new_pos });
+                }
             }
-        };
 
-        if target == None {
-            continue;
-        }
+            let target = new_pos.neighbours()
+                                .filter_map(|pos| board.actors.get(&pos))
+                                .filter(|a| roster.hostile(attacker, a.faction))
+                                .map(|a| (a.hp, a.pos))
+                                .min()
+                                .map(|(_, pos)| pos);
 
-        let target = target.unwrap();
+            let target = match target {
+                Some(target) => target,
+                None => continue,
+            };
 
-        let attack_power = if elf_attacker { elf_attack } else { 3 };
+            let attack_power = roster.factions[attacker].attack;
+
+            let victim = board.actors.get_mut(&target).unwrap();
+            victim.hp -= attack_power;
+            let (victim, hp_left) = (victim.uid, victim.hp);
+            if let Some(log) = log.as_deref_mut() {
+                log.events.push(Event::Attack { round: turn + 1, attacker: actor,
+                                                target: victim, damage: attack_power,
+                                                hp_left });
+                if hp_left <= 0 {
+                    log.events.push(Event::Death { round: turn + 1, actor: victim,
+                                                   pos: target });
+                }
+            }
+            if hp_left <= 0 {
+                board.actors.remove(&target);
+            }
+        }
 
-        actors.entry(target).and_modify(|a| a.hp -= attack_power);
-        if actors[&target].hp <= 0 {
-            actors.remove(&target);
+        turn += 1;
+        if let Some(log) = log.as_deref_mut() {
+            log.boards.push(render(board, roster));
         }
+    }
 
+    if let Some(log) = log {
+        log.summary = summarise(&initial, board, &log.events, roster);
     }
 
-    let total_hp = actors.iter().map(|(_, a)| a.hp).sum();
-    (turn, total_hp)
+    (turn, board.total_hp())
 }
 
-fn parse_input(input: String) -> (Vec<Vec<bool>>, HashMap<usize, Actor>) {
+/// Units are placed wherever a faction's symbol appears on the map.
+fn parse_input(input: String, roster: &Roster) -> Board {
     let mut walls = Vec::new();
-    let mut actors = HashMap::new();
+    let mut actors = BTreeMap::new();
 
-    let mut y = 0; 
-    for line in input.lines() {
+    for (y, line) in input.lines().enumerate() {
         walls.push(Vec::new());
 
-        let mut x = 0;
-        for byte in line.bytes() {
+        for (x, byte) in line.bytes().enumerate() {
             let next_tile = match byte as char {
                 '#' => true,
                 '.' => false,
-                'E' | 'G' => {
-                    let actor = Actor::new(byte as char == 'E', x, y);
-                    actors.insert(actor.uid, actor);
-                    false
+                symbol => match roster.faction_of(symbol) {
+                    Some(faction) => {
+                        let actor = Actor::new(actors.len(), faction,
+                                               roster.factions[faction].hp, x, y);
+                        actors.insert(actor.pos, actor);
+                        false
+                    },
+                    None => panic!("Unknown symbol: {}", symbol),
                 },
-                _ => panic!("Unknown symbol: {}", byte as char),
             };
             walls.last_mut().unwrap()
                    .push(next_tile);
-            x += 1;
         }
-        y += 1;
     }
-    (walls, actors)
+    Board { walls, actors }
+}
+
+/// A battle fought with the searched faction at `attack` power.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+struct PowerTrial {
+    attack: i32,
+    deaths: usize,
+    turn: u32,
+    total_hp: i32,
+}
+
+impl PowerTrial {
+    fn outcome(&self) -> i64 {
+        i64::from(self.turn) * i64::from(self.total_hp)
+    }
+}
+
+/// Every power tried, in order, and the lowest with no deaths. If deaths
+/// didn't fall as power rose, every power up to the winner was tried.
+#[derive(Debug, Clone)]
+struct PowerSearch {
+    trials: Vec<PowerTrial>,
+    winner: PowerTrial,
+    monotone: bool,
+}
+
+fn trial(board: &Board, roster: &Roster, faction: usize, attack: i32) -> PowerTrial {
+    let mut roster = roster.clone();
+    roster.factions[faction].attack = attack;
+    let mut board = board.clone();
+    let units = board.count_faction(faction);
+    let (turn, total_hp) = run(&mut board, &roster);
+    PowerTrial { attack, deaths: units - board.count_faction(faction), turn, total_hp }
 }
 
-fn count_elves(actors: &HashMap<usize, Actor>) -> usize {
-    actors.iter().filter(|(_, a)| a.is_elf).count()
+/// Battles at each of `powers`, spread over `threads` threads.
+fn trials(board: &Board, roster: &Roster, faction: usize, powers: &[i32],
+          threads: usize) -> Vec<PowerTrial> {
+    let chunk_size = powers.len().div_ceil(threads.max(1)).max(1);
+    thread::scope(|scope| {
+        let handles: Vec<_> = powers.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || {
+                chunk.iter()
+                     .map(|&attack| trial(board, roster, faction, attack))
+                     .collect::<Vec<_>>()
+            }))
+            .collect();
+        handles.into_iter()
+               .flat_map(|handle| handle.join().unwrap())
+               .collect()
+    })
 }
 
-fn find_winning_power(walls: &Vec<Vec<bool>>,
-                      actors: &HashMap<usize, Actor>) -> (i32, u32, i32) {
-    let mut attack_power = 3;
-    loop {
-        let mut actors = actors.clone();
-        let elves = count_elves(&actors);
-        let (turn, total_hp) = run(walls, &mut actors, attack_power);
-        if elves == count_elves(&actors) {
-            return (attack_power, turn, total_hp);
+/// Lowest attack power, from the faction's own, that sees none of its
+/// units die. Powers rise exponentially until one wins, then the gap
+/// below it is narrowed, trying up to `threads` powers at once.
+fn search_power(board: &Board, roster: &Roster, faction: usize,
+                threads: usize) -> PowerSearch {
+    let threads = threads.max(1);
+    let base = roster.factions[faction].attack;
+    let mut tested = BTreeMap::new();
+    let run_trials = |powers: Vec<i32>, tested: &mut BTreeMap<i32, PowerTrial>| {
+        let untested: Vec<i32> = powers.into_iter()
+                                       .filter(|power| !tested.contains_key(power))
+                                       .collect();
+        for trial in trials(board, roster, faction, &untested, threads) {
+            tested.insert(trial.attack, trial);
         }
-        attack_power += 1;
+    };
+    let lowest_win = |tested: &BTreeMap<i32, PowerTrial>| {
+        tested.values().find(|trial| trial.deaths == 0).map(|trial| trial.attack)
+    };
+
+    // Offsets from the base power of 0, 1, 2, 4, 8...
+    let mut step = 0;
+    let mut hi = loop {
+        let powers = (step..step + threads as u32)
+            .map(|k| {
+                let offset = if k == 0 { 0 } else { 1i32.checked_shl(k - 1)
+                                                        .filter(|&o| o > 0)
+                                                        .expect("No attack power wins.") };
+                base.checked_add(offset).expect("No attack power wins.")
+            })
+            .collect();
+        step += threads as u32;
+        run_trials(powers, &mut tested);
+        if let Some(hi) = lowest_win(&tested) {
+            break hi;
+        }
+    };
+
+    let highest_loss_below = |tested: &BTreeMap<i32, PowerTrial>, hi: i32| {
+        tested.range(..hi).rev()
+              .find(|&(_, trial)| trial.deaths > 0)
+              .map_or(base - 1, |(&attack, _)| attack)
+    };
+    let mut lo = highest_loss_below(&tested, hi);
+    while hi - lo > 1 {
+        let probes = (threads as i32).min(hi - lo - 1);
+        let powers = (1..=probes).map(|i| lo + (hi - lo) * i / (probes + 1)).collect();
+        run_trials(powers, &mut tested);
+        hi = lowest_win(&tested).unwrap();
+        lo = highest_loss_below(&tested, hi);
     }
+
+    let monotone = tested.values()
+                         .zip(tested.values().skip(1))
+                         .all(|(lower, higher)| higher.deaths <= lower.deaths);
+    if !monotone {
+        run_trials((base..hi).collect(), &mut tested);
+        hi = lowest_win(&tested).unwrap();
+    }
+
+    PowerSearch { winner: tested[&hi], trials: tested.into_values().collect(), monotone }
+}
+
+fn symbol_arg<I: Iterator<Item = String>>(args: &mut I) -> char {
+    args.next().and_then(|symbol| symbol.chars().next()).expect("Missing symbol.")
 }
 
 fn main() {
+    // Optional arguments: --log, --powers, --faction <symbol> <name> <hp>
+    // <attack> to add or redefine a faction, and --allies <symbol> <symbol>
+    let mut log_battle = false;
+    let mut show_powers = false;
+    let mut roster = Roster::standard();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--log" => log_battle = true,
+            "--powers" => show_powers = true,
+            "--faction" => {
+                let symbol = symbol_arg(&mut args);
+                let name = args.next().expect("Missing faction name.");
+                let mut stat = || args.next()
+                                      .and_then(|stat| stat.parse().ok())
+                                      .expect("Missing HP or attack.");
+                let faction = Faction { name, symbol, hp: stat(), attack: stat() };
+                match roster.faction_of(symbol) {
+                    Some(index) => roster.factions[index] = faction,
+                    None => { roster.add_faction(faction); },
+                }
+            },
+            "--allies" => {
+                let a = roster.faction_of(symbol_arg(&mut args)).expect("Unknown faction.");
+                let b = roster.faction_of(symbol_arg(&mut args)).expect("Unknown faction.");
+                roster.set_hostile(a, b, false);
+            },
+            _ => panic!("Unknown argument: {}", arg),
+        }
+    }
+
     let mut input = String::new();
     let mut f = File::open("input").expect("Failed to open input.");
     f.read_to_string(&mut input).expect("Failed to read input.");
 
-    let (walls, mut actors) = parse_input(input);
-    let (power, turn_nl, hp_nl) = find_winning_power(&walls, &actors);
-    let (turn, total_hp) = run(&walls, &mut actors, 3);
+    let mut board = parse_input(input, &roster);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let search = search_power(&board, &roster, ELVES, threads);
+    if show_powers {
+        for trial in &search.trials {
+            println!("Attack {}: {} elves died, outcome {}", trial.attack,
+                     trial.deaths, trial.outcome());
+        }
+        if !search.monotone {
+            println!("Deaths don't fall steadily with attack power");
+        }
+    }
+    let (turn, total_hp, log) = run_logged(&mut board, &roster);
+    if log_battle {
+        print!("{}", log);
+    }
     println!("Ended at turn {}, with a total HP of {}", turn, total_hp);
     println!("Product: {}", turn as i32 *total_hp);
-    println!("No losses at {} attack power with outcome of {}", power, turn_nl as i32*hp_nl);
+    println!("No losses at {} attack power with outcome of {}", search.winner.attack,
+             search.winner.outcome());
 }